@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flexcore::test::bench::{BenchChannel, BenchPipeline};
+
+const MESSAGES: usize = 10_000;
+
+fn pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    for channel in [BenchChannel::Unbounded, BenchChannel::Bounded(64)] {
+        for fan_out in [1, 4, 16] {
+            let pipeline = BenchPipeline::new().with_fan_out(fan_out).with_channel(channel);
+            group.throughput(Throughput::Elements((MESSAGES * fan_out) as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{channel:?}"), fan_out), &pipeline, |b, pipeline| {
+                b.iter_custom(|iters| {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let result = pipeline.run(MESSAGES).expect("Cannot build pipeline");
+                        assert_eq!(result.received, MESSAGES * fan_out, "Pipeline timed out");
+                        elapsed += result.elapsed;
+                    }
+                    elapsed
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
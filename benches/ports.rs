@@ -0,0 +1,26 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flexcore::{Input, Output};
+
+const BATCHES: [usize; 3] = [16, 256, 4096];
+
+fn fetch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fetch");
+    for batch in BATCHES {
+        group.throughput(Throughput::Elements(batch as u64));
+        for (name, hint) in [("no_hint", 0), ("capacity_hint", batch)] {
+            let mut output = Output::<u64>::default();
+            let mut input = Input::with_capacity_hint(hint);
+            output.connect(&mut input);
+            group.bench_function(BenchmarkId::new(name, batch), |b| {
+                b.iter(|| {
+                    output.fire_batch(0..batch as u64);
+                    input.fetch()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fetch);
+criterion_main!(benches);
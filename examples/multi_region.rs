@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use flexcore::*;
+use flexcore::prelude::*;
 
 #[derive(Default, Debug, Clone)]
 struct SensorData {
@@ -35,7 +35,6 @@ impl Node for SensorInterface {
             data: [v, v + 1.0, v + 2.0],
         });
     }
-    fn process_input(&mut self) {}
 }
 struct Processing {
     name: String,
@@ -66,7 +65,7 @@ impl Node for Processing {
 
         for d in data {
             // only this inner part should be user-specified.
-            let velocity = d.data[0] as f64 * d.data[1] as f64 * d.data[2] as f64;
+            let velocity = d.data[0] * d.data[1] * d.data[2];
             println!("{:?} -> {}", d, velocity);
             self.out_velocity.fire(velocity);
         }
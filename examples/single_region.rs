@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use flexcore::*;
+use flexcore::prelude::*;
 
 #[derive(Default, Debug, Clone)]
 struct SensorData {
@@ -35,20 +35,24 @@ impl Node for SensorInterface {
             data: [v, v + 1.0, v + 2.0],
         });
     }
-    fn process_input(&mut self) {}
 }
+ports! {
+    struct ProcessingPorts {
+        in: [measurements: SensorData],
+        out: [velocity: f64],
+    }
+}
+
 struct Processing {
     name: String,
-    in_measurements: Input<SensorData>,
-    out_velocity: Output<f64>,
+    ports: ProcessingPorts,
 }
 
 impl Processing {
     fn new(name: impl Into<String>) -> Self {
         Self {
             name: name.into(),
-            in_measurements: Input::default(),
-            out_velocity: Output::default(),
+            ports: ProcessingPorts::default(),
         }
     }
 }
@@ -57,18 +61,24 @@ impl Node for Processing {
     fn name(&self) -> &String {
         &self.name
     }
+    fn input_port(&mut self, name: &str) -> Option<&mut dyn flexcore::ports::AnyInput> {
+        self.ports.input_port(name)
+    }
+    fn output_port(&mut self, name: &str) -> Option<&mut dyn flexcore::ports::AnyOutput> {
+        self.ports.output_port(name)
+    }
     fn process_input(&mut self) {
         // This should happen automatically.
         // Ideally, I'd want a trait
         //
         // fn process_input(&mut self, data: T);
-        let data = self.in_measurements.fetch();
+        let data = self.ports.measurements.fetch();
 
         for d in data {
             // only this inner part should be user-specified.
-            let velocity = d.data[0] as f64 * d.data[1] as f64 * d.data[2] as f64;
+            let velocity = d.data[0] * d.data[1] * d.data[2];
             println!("{:?} -> {}", d, velocity);
-            self.out_velocity.fire(velocity);
+            self.ports.velocity.fire(velocity);
         }
     }
 }
@@ -110,10 +120,11 @@ fn main() {
 
     sensor_interface
         .out_measurements
-        .connect(&mut processing.in_measurements);
+        .connect(&mut processing.ports.measurements);
 
     processing
-        .out_velocity
+        .ports
+        .velocity
         .connect(&mut business_logic.in_velocity);
 
     let infra = InfrastructureBuilder::default();
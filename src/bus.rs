@@ -0,0 +1,117 @@
+//! Publish/subscribe wiring of ports by topic.
+//!
+//! Instead of connecting an `Output` to each `Input` directly, outputs publish to a topic of a
+//! `Bus` and inputs subscribe to it. Every item fired on a publishing output reaches every
+//! input subscribed to the topic, no matter in which order they joined. Each topic carries a
+//! single data type, set by its first publisher or subscriber.
+//!
+//! # Coexistence with direct connections
+//!
+//! A port may be connected directly and use topics at the same time. `Output::fire` and
+//! `Output::fire_blocking` deliver to the direct connections and to all subscribers of the
+//! port's topics, never waiting for space on the latter. `Output::fire_to` only addresses
+//! direct connections. `Output::connection_count` and `Output::is_closed` only consider direct
+//! connections. On the input side, a subscription is a connection like any other: it is read by
+//! `Input::fetch`, has its own sequence numbers and counts towards `Node::max_items_per_tick`.
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::ports::{channel, Sink};
+use crate::{ConnectionId, FlexcoreError, Input, Output};
+
+/// Connections to the inputs subscribed to a topic.
+pub(crate) type Subscribers<T> = Arc<Mutex<Vec<Sink<T>>>>;
+
+/// Deliver `t` to all subscribers of `topic`, dropping those whose input port is gone.
+pub(crate) fn publish<T: Clone>(topic: &Subscribers<T>, t: &T) {
+    let mut subscribers = topic.lock().unwrap_or_else(|e| e.into_inner());
+    subscribers.retain_mut(|sink| sink.send(t.clone()));
+}
+
+struct Topic {
+    /// Data type of the topic, see `std::any::type_name`
+    type_name: &'static str,
+    /// `Subscribers` of the data type
+    subscribers: Box<dyn Any + Send>,
+}
+
+/// Routes data from publishing outputs to subscribed inputs by topic, see the module
+/// documentation.
+///
+/// Cheap to clone, all clones share the same topics. Each infrastructure owns one, see
+/// `InfrastructureBuilder::bus`, but independent buses can be created as well.
+#[derive(Clone, Default)]
+pub struct Bus {
+    topics: Arc<Mutex<HashMap<String, Topic>>>,
+}
+
+impl Bus {
+    /// Names of all topics used so far, in no particular order.
+    pub fn topics(&self) -> Vec<String> {
+        let topics = self.topics.lock().unwrap_or_else(|e| e.into_inner());
+        topics.keys().cloned().collect()
+    }
+
+    /// Subscribers of `topic`, creating it with data type `T` if it does not exist yet.
+    ///
+    /// `Err` holds the data type of the existing topic.
+    fn subscribers<T: Send + 'static>(&self, topic: &str) -> Result<Subscribers<T>, &'static str> {
+        let mut topics = self.topics.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = topics.entry(topic.to_string()).or_insert_with(|| Topic {
+            type_name: std::any::type_name::<T>(),
+            subscribers: Box::new(Subscribers::<T>::default()),
+        });
+        entry
+            .subscribers
+            .downcast_ref::<Subscribers<T>>()
+            .cloned()
+            .ok_or(entry.type_name)
+    }
+}
+
+impl<T: Clone + Send + 'static> Output<T> {
+    /// Deliver everything fired on this port to the inputs subscribed to `topic` of `bus`.
+    ///
+    /// Fails with `FlexcoreError::PortTypeMismatch` if the topic carries another data type.
+    /// Publishing to the same topic twice delivers every item twice.
+    pub fn publish(&mut self, bus: &Bus, topic: &str) -> Result<(), FlexcoreError> {
+        let subscribers = bus.subscribers::<T>(topic).map_err(|topic_type| {
+            log::error!(
+                "Cannot publish {} to topic {topic} of type {topic_type}",
+                std::any::type_name::<T>()
+            );
+            FlexcoreError::PortTypeMismatch {
+                output_type: std::any::type_name::<T>(),
+                input_type: topic_type,
+            }
+        })?;
+        self.topics.push(subscribers);
+        Ok(())
+    }
+}
+
+impl<T: Send + 'static> Input<T> {
+    /// Receive everything fired on outputs publishing to `topic` of `bus`, from now on.
+    ///
+    /// Fails with `FlexcoreError::PortTypeMismatch` if the topic carries another data type.
+    /// Returns the id of the new connection.
+    pub fn subscribe(&mut self, bus: &Bus, topic: &str) -> Result<ConnectionId, FlexcoreError> {
+        let subscribers = bus.subscribers::<T>(topic).map_err(|topic_type| {
+            log::error!(
+                "Cannot subscribe {} to topic {topic} of type {topic_type}",
+                std::any::type_name::<T>()
+            );
+            FlexcoreError::PortTypeMismatch {
+                output_type: topic_type,
+                input_type: std::any::type_name::<T>(),
+            }
+        })?;
+        let (sink, mut source) = channel();
+        let id = sink.id;
+        source.label = Some(topic.to_string());
+        subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(sink);
+        self.sources.push(source);
+        Ok(id)
+    }
+}
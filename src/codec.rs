@@ -0,0 +1,124 @@
+//! Serialization formats of the features transferring or storing data as bytes.
+//!
+//! Network connections (see `Output::connect_remote`) and spill files (see `spill::SpillOutput`)
+//! encode their data with a `Codec`. Per default, this is `Format::Bincode`. Choose another
+//! format per connection with the `*_with_codec` variants of their methods, for the connections
+//! of one infrastructure with `InfrastructureBuilder::with_default_codec`, or for all connections
+//! made afterwards with `set_default_format`.
+//!
+//! Both ends of a network connection must use the same format, it is not negotiated.
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Converts data to bytes and back.
+///
+/// Implement it for formats not provided by flexcore. Codecs are used from background threads,
+/// so they must be `Send + Sync`.
+pub trait Codec: Send + Sync + 'static {
+    /// Serialize `t` into a new buffer.
+    ///
+    /// The result must be readable by `Self::decode` of an equal codec, also in another process.
+    fn encode<T: Serialize>(&self, t: &T) -> Result<Vec<u8>, CodecError>;
+
+    /// Deserialize a `T` from `bytes` as written by `Self::encode`.
+    ///
+    /// `bytes` may come from a peer or a file, so return an error on invalid data instead of
+    /// panicking.
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+/// Error of a `Codec`, e.g. corrupt data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError(pub String);
+
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Formats provided by flexcore.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Format {
+    /// Compact binary format of `bincode` 1, the default.
+    #[default]
+    Bincode,
+    /// JSON, readable by external tools and for debugging. Largest and slowest.
+    #[cfg(feature = "json")]
+    Json,
+    /// Compact binary format of `postcard`, for resource-constrained peers.
+    #[cfg(feature = "postcard")]
+    Postcard,
+}
+
+impl Codec for Format {
+    fn encode<T: Serialize>(&self, t: &T) -> Result<Vec<u8>, CodecError> {
+        let err = |e: &dyn std::fmt::Display| CodecError(e.to_string());
+        match self {
+            Self::Bincode => bincode::serialize(t).map_err(|e| err(&e)),
+            #[cfg(feature = "json")]
+            Self::Json => serde_json::to_vec(t).map_err(|e| err(&e)),
+            #[cfg(feature = "postcard")]
+            Self::Postcard => postcard::to_allocvec(t).map_err(|e| err(&e)),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        let err = |e: &dyn std::fmt::Display| CodecError(e.to_string());
+        match self {
+            Self::Bincode => bincode::deserialize(bytes).map_err(|e| err(&e)),
+            #[cfg(feature = "json")]
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| err(&e)),
+            #[cfg(feature = "postcard")]
+            Self::Postcard => postcard::from_bytes(bytes).map_err(|e| err(&e)),
+        }
+    }
+}
+
+thread_local! {
+    /// Format of the infrastructure whose regions run on this thread, see
+    /// `InfrastructureBuilder::with_default_codec`.
+    static THREAD_FORMAT: Cell<Option<Format>> = const { Cell::new(None) };
+}
+
+/// Index of the default format in `FORMATS`.
+static DEFAULT_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// All formats, indexed by `DEFAULT_FORMAT`.
+const FORMATS: &[Format] = &[
+    Format::Bincode,
+    #[cfg(feature = "json")]
+    Format::Json,
+    #[cfg(feature = "postcard")]
+    Format::Postcard,
+];
+
+/// Format of connections that are made without a codec, see the module documentation.
+///
+/// On a region thread of an infrastructure with a format of its own (see
+/// `InfrastructureBuilder::with_default_codec`), this is that format, otherwise the one of
+/// `set_default_format`.
+pub fn default_format() -> Format {
+    THREAD_FORMAT
+        .with(Cell::get)
+        .unwrap_or_else(|| FORMATS[usize::from(DEFAULT_FORMAT.load(Ordering::Relaxed))])
+}
+
+/// Set by the infrastructure for its region threads, see `default_format`.
+pub(crate) fn set_thread_format(format: Option<Format>) {
+    THREAD_FORMAT.with(|f| f.set(format));
+}
+
+/// Use `format` for all connections made from now on without a codec, in the whole process.
+///
+/// Connections made before keep their format. Infrastructures with a format of their own (see
+/// `InfrastructureBuilder::with_default_codec`) are not affected.
+pub fn set_default_format(format: Format) {
+    let index = FORMATS.iter().position(|f| *f == format).unwrap_or_default();
+    DEFAULT_FORMAT.store(u8::try_from(index).unwrap_or_default(), Ordering::Relaxed);
+}
@@ -0,0 +1,363 @@
+//! Threads running one or more regions.
+use std::sync::mpsc::Receiver;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::region::{Region, RegionCommand, RestartPolicy};
+use crate::shutdown::{RunningGuard, Shutdown};
+use crate::startup::{Startup, StartingGuard};
+
+/// A region together with everything its thread needs to run it.
+pub(crate) struct Scheduled {
+    pub(crate) region: Region,
+    pub(crate) commands: Receiver<RegionCommand>,
+    pub(crate) running: RunningGuard,
+    /// Dropped once the region has started
+    pub(crate) starting: Option<StartingGuard>,
+    /// Time of the next iteration
+    pub(crate) due: Instant,
+}
+
+/// Longest sleep of a thread whose regions wait for their trigger, see `LoopMode::Triggered`.
+const TRIGGER_IDLE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Longest sleep of a thread whose regions wait to be restarted, see `RestartPolicy::Always`.
+const RESTART_IDLE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Barrier synchronizing the scheduler passes of all executor threads.
+///
+/// Unlike `std::sync::Barrier`, threads can leave, so a stopping thread does not block the others
+/// forever.
+#[derive(Debug)]
+pub(crate) struct TickBarrier {
+    state: Mutex<BarrierState>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct BarrierState {
+    /// Threads taking part
+    participants: usize,
+    /// Threads waiting for the current generation
+    arrived: usize,
+    /// Incremented each time the waiting threads are released
+    generation: u64,
+}
+
+impl TickBarrier {
+    pub(crate) fn new(participants: usize) -> Self {
+        Self {
+            state: Mutex::new(BarrierState {
+                participants,
+                arrived: 0,
+                generation: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Block until all participating threads have called `wait`.
+    ///
+    /// Meanwhile, the thread counts as held by `pause`, as it is between two passes like a
+    /// parked one. Otherwise pausing would wait for it while it waits for a parked thread. Like
+    /// a parked thread, it calls `while_held` regularly.
+    fn wait(&self, pause: Option<&PauseGate>, mut while_held: impl FnMut()) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.arrived += 1;
+        if state.arrived >= state.participants {
+            Self::release(&mut state);
+            self.released.notify_all();
+            return;
+        }
+        if let Some(pause) = pause {
+            pause.set_held(true);
+        }
+        let generation = state.generation;
+        while state.generation == generation {
+            if pause.is_none() {
+                state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+            drop(state);
+            while_held();
+            state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.generation != generation {
+                break;
+            }
+            state = self
+                .released
+                .wait_timeout(state, PAUSE_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        if let Some(pause) = pause {
+            pause.set_held(false);
+        }
+    }
+
+    /// Stop taking part, releasing the others if they only waited for this thread.
+    pub(crate) fn leave(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.participants = state.participants.saturating_sub(1);
+        if state.arrived > 0 && state.arrived >= state.participants {
+            Self::release(&mut state);
+            self.released.notify_all();
+        }
+    }
+
+    fn release(state: &mut BarrierState) {
+        state.arrived = 0;
+        state.generation = state.generation.wrapping_add(1);
+    }
+}
+
+/// Leaves the barrier when dropped, even if the thread panics.
+struct BarrierGuard<'a>(&'a TickBarrier);
+
+impl Drop for BarrierGuard<'_> {
+    fn drop(&mut self) {
+        self.0.leave();
+    }
+}
+
+/// Interval in which paused threads check for shutdown and commands.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Holds all executor threads between their scheduler passes, see `Infrastructure::pause_all`.
+#[derive(Debug, Default)]
+pub(crate) struct PauseGate {
+    state: Mutex<PauseState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PauseState {
+    requested: bool,
+    /// Threads taking part
+    participants: usize,
+    /// Threads currently held
+    parked: usize,
+}
+
+impl PauseGate {
+    pub(crate) fn new(participants: usize) -> Self {
+        Self {
+            state: Mutex::new(PauseState {
+                participants,
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Ask all threads to pause and block until they are held or shutdown is requested.
+    pub(crate) fn pause(&self, shutdown: &Shutdown) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requested = true;
+        // Threads waiting for their next iteration pause right away.
+        shutdown.notify_change();
+        while state.parked < state.participants && !shutdown.is_requested() {
+            state = self
+                .changed
+                .wait_timeout(state, PAUSE_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Let all threads continue.
+    pub(crate) fn resume(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).requested = false;
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).requested
+    }
+
+    /// Hold the calling thread while a pause is requested, unless shutdown is requested.
+    ///
+    /// Calls `while_parked` regularly meanwhile, e.g. to run commands of the regions.
+    fn park(&self, shutdown: &Shutdown, mut while_parked: impl FnMut()) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.requested {
+            return;
+        }
+        state.parked += 1;
+        self.changed.notify_all();
+        while state.requested && !shutdown.is_requested() {
+            drop(state);
+            while_parked();
+            state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if !state.requested {
+                break;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, PAUSE_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        state.parked -= 1;
+    }
+
+    /// Count the calling thread as held while `held`, e.g. while it waits at a `TickBarrier`.
+    fn set_held(&self, held: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if held {
+            state.parked += 1;
+        } else {
+            state.parked -= 1;
+        }
+        self.changed.notify_all();
+    }
+
+    /// Stop taking part, e.g. because all regions of the thread have stopped.
+    pub(crate) fn leave(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.participants = state.participants.saturating_sub(1);
+        self.changed.notify_all();
+    }
+}
+
+/// Leaves the pause gate when dropped, even if the thread panics.
+struct PauseGuard<'a>(&'a PauseGate);
+
+impl Drop for PauseGuard<'_> {
+    fn drop(&mut self) {
+        self.0.leave();
+    }
+}
+
+/// Run `regions` on the current thread until all of them have stopped.
+///
+/// Regions are started in order of their startup phase, each waiting for all regions of lower
+/// phases to be started.
+///
+/// In each pass, every region whose iteration is due runs at most one iteration, in order of
+/// descending priority. A region is skipped if a region of higher priority is due again by then.
+/// Among regions of equal priority, the one that goes first rotates with each pass, so none of
+/// them is systematically delayed by the others.
+///
+/// With a `barrier`, each pass that ran any region ends by waiting for all other executor threads
+/// to finish their pass. With a `pause` gate, the thread is held before a pass while paused.
+pub(crate) fn run(
+    mut regions: Vec<Scheduled>,
+    startup: &Startup,
+    shutdown: &Shutdown,
+    barrier: Option<&TickBarrier>,
+    pause: Option<&PauseGate>,
+) {
+    let _barrier_guard = barrier.map(BarrierGuard);
+    let _pause_guard = pause.map(PauseGuard);
+    regions.sort_by_key(|s| s.region.startup_phase());
+    for scheduled in &mut regions {
+        startup.wait(scheduled.region.startup_phase(), shutdown);
+        scheduled.region.start();
+        scheduled.starting = None;
+        scheduled.due = scheduled.region.align(Instant::now());
+    }
+    let mut first = 0;
+    while !regions.is_empty() {
+        if let Some(pause) = pause {
+            // Commands do not wait for the pause, see `Infrastructure::pause_all`.
+            pause.park(shutdown, || run_commands(&mut regions));
+        }
+        // Taken before stepping, so shutdown requested meanwhile cuts the following sleep short.
+        let generation = shutdown.generation();
+        // Triggered regions are due whenever their trigger has been fired, which also cuts the
+        // sleep short.
+        let now = Instant::now();
+        for scheduled in regions.iter_mut().filter(|s| s.region.is_triggered()) {
+            scheduled.due = if scheduled.region.is_trigger_pending() {
+                now
+            } else {
+                // Commands must not wait for the trigger.
+                scheduled.region.run_commands(&scheduled.commands);
+                now + TRIGGER_IDLE_PERIOD
+            };
+        }
+        // Failed regions only run to stop or once restarted, see `RestartPolicy::Always`.
+        for scheduled in regions.iter_mut().filter(|s| s.region.restart_policy() == RestartPolicy::Always) {
+            if !scheduled.region.is_failed() {
+                continue;
+            }
+            scheduled.due = if scheduled.region.restart_if_requested() {
+                now
+            } else {
+                scheduled.region.run_commands(&scheduled.commands);
+                now + RESTART_IDLE_PERIOD
+            };
+        }
+        let len = regions.len();
+        let mut stopped = Vec::new();
+        let mut ran = false;
+        let mut order: Vec<usize> = (0..len).map(|i| (first + i) % len).collect();
+        // Stable, so regions of equal priority keep their rotation.
+        order.sort_by_key(|&idx| std::cmp::Reverse(regions[idx].region.priority()));
+        for (pos, &idx) in order.iter().enumerate() {
+            let now = Instant::now();
+            // Stopping regions do not wait for their tick.
+            if regions[idx].due > now && !shutdown.may_stop(regions[idx].region.shutdown_phase()) {
+                continue;
+            }
+            let priority = regions[idx].region.priority();
+            let preempted = order[..pos].iter().any(|&other| {
+                regions[other].region.priority() > priority && regions[other].due <= now && !stopped.contains(&other)
+            });
+            if preempted {
+                continue;
+            }
+            let scheduled = &mut regions[idx];
+            ran = true;
+            match step(scheduled, shutdown) {
+                Some(delay) => scheduled.due = Instant::now() + delay,
+                None => stopped.push(idx),
+            }
+        }
+        stopped.sort_unstable();
+        for idx in stopped.into_iter().rev() {
+            let Scheduled { region, running, .. } = regions.remove(idx);
+            // Regions of later phases may stop once all nodes of this one are gone.
+            drop(region);
+            drop(running);
+        }
+        first = first.wrapping_add(1);
+        if let (Some(barrier), true) = (barrier, ran) {
+            if regions.is_empty() {
+                break;
+            }
+            barrier.wait(pause, || run_commands(&mut regions));
+        }
+        if let Some(next) = regions.iter().map(|s| s.due).min() {
+            shutdown.sleep_until(next, generation);
+        }
+    }
+}
+
+/// Run an iteration of `scheduled`, see `Region::step`.
+///
+/// Panics of regions with `RestartPolicy::Always` are caught, the region then waits to be
+/// restarted.
+fn step(scheduled: &mut Scheduled, shutdown: &Shutdown) -> Option<Duration> {
+    if scheduled.region.restart_policy() == RestartPolicy::Never {
+        return scheduled.region.step(&scheduled.commands, shutdown);
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        scheduled.region.step(&scheduled.commands, shutdown)
+    }));
+    match result {
+        Ok(delay) => delay,
+        Err(payload) => {
+            scheduled.region.fail(crate::shutdown::panic_message(payload.as_ref()));
+            Some(RESTART_IDLE_PERIOD)
+        }
+    }
+}
+
+/// Execute the commands of all `regions` between two passes, see `Region::run_commands`.
+fn run_commands(regions: &mut [Scheduled]) {
+    for scheduled in regions {
+        scheduled.region.run_commands(&scheduled.commands);
+    }
+}
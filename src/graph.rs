@@ -0,0 +1,192 @@
+//! Connection graph of an infrastructure as data, see `InfrastructureBuilder::graph`.
+//!
+//! With the `serde` feature, `Graph` can be serialized, e.g. to JSON for external tools.
+use std::collections::HashMap;
+
+use crate::{ConnectionId, InfrastructureBuilder, Region};
+
+/// Nodes and the connections between their named ports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Graph {
+    /// Nodes of all regions, by region in order of addition, then in execution order
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Node of a `Graph`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphNode {
+    pub name: String,
+    pub region: String,
+    /// Named input ports, see `Node::input_port_names`
+    pub inputs: Vec<GraphPort>,
+    /// Named output ports, see `Node::output_port_names`
+    pub outputs: Vec<GraphPort>,
+}
+
+/// Named port of a `GraphNode`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphPort {
+    pub name: String,
+    /// Data type of the port, see `std::any::type_name`
+    pub type_name: String,
+}
+
+/// Connection from an output port to an input port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphEdge {
+    pub connection: ConnectionId,
+    pub from_node: String,
+    pub from_port: String,
+    pub to_node: String,
+    pub to_port: String,
+}
+
+impl Graph {
+    /// Names of the nodes without incoming edges, e.g. sources of data.
+    pub fn roots(&self) -> Vec<&str> {
+        self.nodes_without(|e| &e.to_node)
+    }
+
+    /// Names of the nodes without outgoing edges, e.g. sinks of data.
+    pub fn leaves(&self) -> Vec<&str> {
+        self.nodes_without(|e| &e.from_node)
+    }
+
+    fn nodes_without(&self, end: impl Fn(&GraphEdge) -> &String) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|n| !self.edges.iter().any(|e| *end(e) == n.name))
+            .map(|n| n.name.as_str())
+            .collect()
+    }
+
+    /// Names of all nodes such that each node comes after the nodes it receives data from,
+    /// `None` if the edges form a cycle.
+    ///
+    /// Nodes that do not depend on each other keep the order of `Self::nodes`.
+    pub fn topological_order(&self) -> Option<Vec<&str>> {
+        let mut incoming: HashMap<&str, usize> = self.nodes.iter().map(|n| (n.name.as_str(), 0)).collect();
+        for edge in &self.edges {
+            if let Some(count) = incoming.get_mut(edge.to_node.as_str()) {
+                *count += 1;
+            }
+        }
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while order.len() < self.nodes.len() {
+            let next = self
+                .nodes
+                .iter()
+                .map(|n| n.name.as_str())
+                .find(|name| incoming.get(name) == Some(&0))?;
+            incoming.remove(next);
+            for edge in self.edges.iter().filter(|e| e.from_node == next) {
+                if let Some(count) = incoming.get_mut(edge.to_node.as_str()) {
+                    *count -= 1;
+                }
+            }
+            order.push(next);
+        }
+        Some(order)
+    }
+}
+
+/// Named ports of the nodes added to a builder and their connections, see
+/// `InfrastructureBuilder::graph`.
+///
+/// Recorded while the builder has the nodes at hand, so the graph can be queried without
+/// borrowing the builder mutably for the port lookups.
+#[derive(Default)]
+pub(crate) struct GraphRecord {
+    nodes: Vec<GraphNode>,
+    /// Output ends of the connections of named ports, by connection id
+    senders: HashMap<ConnectionId, (String, String)>,
+    /// Input ends of the connections of named ports
+    receivers: Vec<(ConnectionId, String, String)>,
+}
+
+impl GraphRecord {
+    /// Record the nodes of `region`, which is added to the builder, with the connections made
+    /// so far.
+    pub(crate) fn add_region(&mut self, region: &mut Region) {
+        let region_name = region.name().clone();
+        for node in region.nodes_mut() {
+            let mut graph_node = GraphNode {
+                name: node.name().clone(),
+                region: region_name.clone(),
+                ..Default::default()
+            };
+            for port in node.input_port_names() {
+                let Some(input) = node.input_port(port) else {
+                    continue;
+                };
+                graph_node.inputs.push(GraphPort {
+                    name: port.to_string(),
+                    type_name: input.type_name().into(),
+                });
+                for id in input.connection_ids() {
+                    self.receivers.push((id, graph_node.name.clone(), port.to_string()));
+                }
+            }
+            for port in node.output_port_names() {
+                let Some(output) = node.output_port(port) else {
+                    continue;
+                };
+                graph_node.outputs.push(GraphPort {
+                    name: port.to_string(),
+                    type_name: output.type_name().into(),
+                });
+                for id in output.connection_ids() {
+                    self.senders.insert(id, (graph_node.name.clone(), port.to_string()));
+                }
+            }
+            self.nodes.push(graph_node);
+        }
+    }
+
+    /// Record the connection `id` made by the builder, see `InfrastructureBuilder::connect`.
+    pub(crate) fn add_connection(&mut self, id: ConnectionId, from: (&str, &str), to: (&str, &str)) {
+        self.senders.insert(id, (from.0.to_string(), from.1.to_string()));
+        self.receivers.push((id, to.0.to_string(), to.1.to_string()));
+    }
+
+    /// Take over the records of `other`, see `InfrastructureBuilder::merge`.
+    pub(crate) fn merge(&mut self, other: GraphRecord) {
+        self.nodes.extend(other.nodes);
+        self.senders.extend(other.senders);
+        self.receivers.extend(other.receivers);
+    }
+}
+
+impl InfrastructureBuilder {
+    /// Nodes and connections added so far as data, e.g. to check them against a specification.
+    ///
+    /// Only named ports are included (see `Node::input_port_names` and
+    /// `Node::output_port_names`), with an edge for each connection between two of them, no
+    /// matter whether it has been made with `Self::connect` or with `Output::connect` before
+    /// adding the nodes. Connections to other ports, e.g. remote ones, are not part of the graph.
+    pub fn graph(&self) -> Graph {
+        let record = &self.graph;
+        let mut graph = Graph {
+            nodes: record.nodes.clone(),
+            edges: Vec::new(),
+        };
+        for (connection, to_node, to_port) in &record.receivers {
+            if let Some((from_node, from_port)) = record.senders.get(connection) {
+                graph.edges.push(GraphEdge {
+                    connection: *connection,
+                    from_node: from_node.clone(),
+                    from_port: from_port.clone(),
+                    to_node: to_node.clone(),
+                    to_port: to_port.clone(),
+                });
+            }
+        }
+        graph.edges.sort_by_key(|e| e.connection);
+        graph
+    }
+}
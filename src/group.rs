@@ -0,0 +1,340 @@
+//! Firing several output ports as one consistent set, see `FireBatchGuard`.
+//!
+//! # Guarantee
+//!
+//! All items fired on `Output` ports while a `FireBatchGuard` is alive form a group. Groups are
+//! enqueued while no region of the infrastructure of the firing node is executing a node method
+//! (`Node::tick` or `Node::process_input`), so each such call sees either all items of a group
+//! on its inputs or none of them. Groups of different threads of one infrastructure are enqueued
+//! one after the other. Infrastructures do not coordinate with each other, so nodes of other
+//! infrastructures may see part of a group.
+//!
+//! Exceptions, where a call may see part of a group:
+//!
+//! - The node firing the group from within one of its own methods, and a node waiting in
+//!   `Output::fire_blocking`: while they wait for the group lock or for space, other groups
+//!   may be enqueued on their inputs.
+//! - Reads outside of node methods, e.g. from `Output::subscribe` observers or other threads.
+//! - Connections that do not enqueue synchronously, like remote or spilling ones.
+//!
+//! Groups need an infrastructure: outside of region threads, e.g. when calling node methods
+//! directly in tests, a guard has no effect.
+//!
+//! # Cost
+//!
+//! Starting a group waits until all node methods in progress in the regions of the
+//! infrastructure have returned, and node methods starting meanwhile wait until the group has
+//! been enqueued. Keep groups short and use `Output::fire` in them: `Output::fire_blocking` on a
+//! full bounded connection waits for a consumer that cannot run while the group is alive, and
+//! never returns.
+//!
+//! Until the first group of an infrastructure, its node methods are not tracked at all, each
+//! region iteration only checks once whether groups are in use. The first group additionally
+//! waits for the iterations in progress to end. From then on, each thread marks its node
+//! methods in a flag of its own, which a starting group checks for all threads. So node methods
+//! do not contend with each other, they only pay for an ordered store to their flag and a load
+//! of the rarely written group flag.
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Groups of one infrastructure, shared by its region threads.
+#[derive(Debug, Default)]
+pub(crate) struct Groups {
+    /// Set by the first group, node methods are tracked from then on.
+    used: AtomicBool,
+    /// Set while a group is being enqueued, also serializes groups of different threads.
+    writer: AtomicBool,
+    /// Threads running regions of the infrastructure
+    threads: Mutex<Vec<Weak<ThreadState>>>,
+}
+
+/// What a region thread is doing, as seen by groups of other threads.
+#[derive(Debug, Default)]
+struct ThreadState {
+    /// Executing a node method, only maintained once groups are in use
+    in_call: AtomicBool,
+    /// Executing a region iteration, see `IterationGuard`
+    in_iteration: AtomicBool,
+}
+
+thread_local! {
+    /// Groups of the infrastructure whose regions run on this thread, with the state of the
+    /// thread, see `set_thread_groups`.
+    static CONTEXT: RefCell<Option<(Arc<Groups>, Arc<ThreadState>)>> = const { RefCell::new(None) };
+
+    /// Whether node methods of this thread are tracked, updated at the start of iterations.
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether this thread is executing a region iteration.
+    static IN_ITERATION: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether this thread is executing a node method.
+    static IN_NODE_CALL: Cell<bool> = const { Cell::new(false) };
+
+    /// Number of nested `FireBatchGuard`s alive on this thread.
+    static GROUP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Let groups started on this thread coordinate with the regions of `groups`, set by the
+/// infrastructure for its region threads.
+pub(crate) fn set_thread_groups(groups: Option<Arc<Groups>>) {
+    let context = groups.map(|groups| {
+        let state = Arc::new(ThreadState::default());
+        let mut threads = groups.threads.lock().unwrap_or_else(|e| e.into_inner());
+        threads.retain(|t| t.strong_count() > 0);
+        threads.push(Arc::downgrade(&state));
+        drop(threads);
+        (groups, state)
+    });
+    CONTEXT.with(|c| *c.borrow_mut() = context);
+    TRACKING.with(|t| t.set(false));
+}
+
+/// Run `f` with the groups of this thread, `None` outside of region threads.
+fn with_context<R>(f: impl FnOnce(&Groups, &ThreadState) -> R) -> Option<R> {
+    CONTEXT.with(|c| c.borrow().as_ref().map(|(groups, state)| f(groups, state)))
+}
+
+/// Wait a little longer each attempt.
+fn backoff(attempt: &mut u32) {
+    *attempt += 1;
+    if *attempt < 16 {
+        std::thread::yield_now();
+    } else {
+        std::thread::sleep(std::time::Duration::from_micros(50));
+    }
+}
+
+fn enter_iteration() {
+    IN_ITERATION.with(|i| i.set(true));
+    if TRACKING.with(Cell::get) {
+        // Groups stay in use, only the first one looks at iterations.
+        return;
+    }
+    let tracking = with_context(|groups, state| {
+        // Both sequentially consistent, pairs with the first `FireBatchGuard::new`: either the
+        // group sees the iteration, or the iteration sees that groups are in use.
+        state.in_iteration.store(true, Ordering::SeqCst);
+        groups.used.load(Ordering::SeqCst)
+    });
+    TRACKING.with(|t| t.set(tracking.unwrap_or(false)));
+}
+
+fn leave_iteration() {
+    IN_ITERATION.with(|i| i.set(false));
+    with_context(|_, state| state.in_iteration.store(false, Ordering::Release));
+}
+
+fn enter_node_call() {
+    IN_NODE_CALL.with(|c| c.set(true));
+    if !TRACKING.with(Cell::get) {
+        return;
+    }
+    with_context(|groups, state| {
+        let mut attempt = 0;
+        loop {
+            // Both sequentially consistent, pairs with `FireBatchGuard::new`: either the group
+            // sees the flag, or this call sees the group.
+            state.in_call.store(true, Ordering::SeqCst);
+            if !groups.writer.load(Ordering::SeqCst) {
+                return;
+            }
+            state.in_call.store(false, Ordering::Release);
+            while groups.writer.load(Ordering::Acquire) {
+                backoff(&mut attempt);
+            }
+        }
+    });
+}
+
+fn leave_node_call() {
+    IN_NODE_CALL.with(|c| c.set(false));
+    if TRACKING.with(Cell::get) {
+        with_context(|_, state| state.in_call.store(false, Ordering::Release));
+    }
+}
+
+impl Groups {
+    /// Wait until no other thread is executing a region iteration.
+    fn wait_for_iterations(&self, own: &ThreadState) {
+        self.wait_for_threads(own, |state| &state.in_iteration);
+    }
+
+    /// Wait until no other thread is executing a node method, `writer` must be set.
+    fn wait_for_node_calls(&self, own: &ThreadState) {
+        self.wait_for_threads(own, |state| &state.in_call);
+    }
+
+    fn wait_for_threads(&self, own: &ThreadState, flag: impl Fn(&ThreadState) -> &AtomicBool) {
+        let threads: Vec<Arc<ThreadState>> = self
+            .threads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        let mut attempt = 0;
+        for state in threads.iter().filter(|s| !std::ptr::eq(s.as_ref(), own)) {
+            while flag(state).load(Ordering::SeqCst) {
+                backoff(&mut attempt);
+            }
+        }
+    }
+}
+
+/// Marks a region iteration in progress, used by the region loop.
+pub(crate) struct IterationGuard(bool);
+
+impl IterationGuard {
+    /// Check whether groups are in use and mark an iteration in progress until dropped.
+    pub(crate) fn enter() -> Self {
+        let nested = IN_ITERATION.with(Cell::get);
+        if !nested {
+            enter_iteration();
+        }
+        Self(!nested)
+    }
+}
+
+impl Drop for IterationGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            leave_iteration();
+        }
+    }
+}
+
+/// Marks a node method in progress, used by the region loop.
+pub(crate) struct NodeCallGuard {
+    /// Iteration of a node method called outside of one, e.g. `Node::on_start`
+    _iteration: IterationGuard,
+}
+
+impl NodeCallGuard {
+    /// Wait until no group is being enqueued, then mark a node method in progress until dropped.
+    pub(crate) fn enter() -> Self {
+        let iteration = IterationGuard::enter();
+        enter_node_call();
+        Self { _iteration: iteration }
+    }
+}
+
+impl Drop for NodeCallGuard {
+    fn drop(&mut self) {
+        leave_node_call();
+    }
+}
+
+/// Resumes a paused iteration and node method on drop, see `pause_node_call`.
+struct Resume {
+    iteration: bool,
+    call: bool,
+}
+
+impl Drop for Resume {
+    fn drop(&mut self) {
+        if self.iteration {
+            enter_iteration();
+        }
+        if self.call {
+            enter_node_call();
+        }
+    }
+}
+
+/// Stop marking the current iteration and node method as in progress while waiting, so groups
+/// can be enqueued.
+fn pause() -> Resume {
+    let outside_group = GROUP_DEPTH.with(Cell::get) == 0;
+    let resume = Resume {
+        iteration: outside_group && IN_ITERATION.with(Cell::get),
+        call: outside_group && IN_NODE_CALL.with(Cell::get),
+    };
+    if resume.call {
+        leave_node_call();
+    }
+    if resume.iteration {
+        leave_iteration();
+    }
+    resume
+}
+
+/// Run `f`, e.g. waiting for space in a connection, without blocking groups meanwhile.
+pub(crate) fn pause_node_call<R>(f: impl FnOnce() -> R) -> R {
+    let _resume = pause();
+    f()
+}
+
+/// Makes all items fired on `Output` ports while alive visible to node methods as one set.
+///
+/// See the module documentation for the exact guarantee and its cost. Guards may be nested,
+/// the group ends with the outermost one. The `fire_group!` macro fires a list of ports in a
+/// group.
+///
+/// ```ignore
+/// let _group = FireBatchGuard::new();
+/// self.out_position.fire(position);
+/// self.out_velocity.fire(velocity);
+/// ```
+pub struct FireBatchGuard {
+    /// Groups locked by the outermost guard
+    groups: Option<Arc<Groups>>,
+    /// Iteration and node method paused by the outermost guard
+    resume: Option<Resume>,
+    /// The group belongs to the current thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl FireBatchGuard {
+    /// Start a group, waiting until no node method of the infrastructure is in progress.
+    pub fn new() -> Self {
+        let depth = GROUP_DEPTH.with(Cell::get);
+        let mut ret = Self {
+            groups: None,
+            resume: None,
+            _not_send: PhantomData,
+        };
+        if depth == 0 {
+            ret.resume = Some(pause());
+            ret.groups = CONTEXT.with(|c| {
+                let context = c.borrow();
+                let (groups, own) = context.as_ref()?;
+                let mut attempt = 0;
+                while groups
+                    .writer
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+                    .is_err()
+                {
+                    backoff(&mut attempt);
+                }
+                if !groups.used.swap(true, Ordering::SeqCst) {
+                    // Iterations that started before did not track their node methods.
+                    groups.wait_for_iterations(own);
+                }
+                groups.wait_for_node_calls(own);
+                Some(groups.clone())
+            });
+        }
+        GROUP_DEPTH.with(|d| d.set(depth + 1));
+        ret
+    }
+}
+
+impl Default for FireBatchGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FireBatchGuard {
+    fn drop(&mut self) {
+        GROUP_DEPTH.with(|d| d.set(d.get() - 1));
+        if let Some(groups) = self.groups.take() {
+            groups.writer.store(false, Ordering::Release);
+        }
+        // Resume the node method only after the group has ended.
+        self.resume.take();
+    }
+}
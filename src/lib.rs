@@ -1,24 +1,263 @@
-use std::sync::atomic::AtomicBool;
+use std::collections::HashMap;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::Instant;
 
 pub mod region;
-pub use region::Region;
+pub use region::{LoopMode, NodeGroup, Region, RestartPolicy, Trigger};
 
 pub mod node;
-pub use node::Node;
+pub use node::{AsAny, Node, Reconfigurable};
+
+pub mod metrics;
+
+pub mod nodes;
+
+pub mod prelude;
+
+mod macros;
 
 pub mod ports;
-pub use ports::{Input, Output};
-use region::RegionBuilder;
+pub use ports::{
+    ConnectionHandle, ConnectionId, CrossRegionPolicy, FairInput, FireError, GatedConnection, Input, InputArray, Output,
+};
+
+#[cfg(feature = "network")]
+pub mod network;
+
+#[cfg(feature = "spill")]
+pub mod spill;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
+#[cfg(feature = "serde")]
+pub mod serde_output;
+
+mod executor;
+
+mod shutdown;
+
+mod startup;
+
+mod supervisor;
+use shutdown::Shutdown;
+pub use shutdown::{RegionExit, ShutdownReason, ShutdownReport, ShutdownToken};
+
+pub mod bus;
+pub use bus::Bus;
+
+pub mod group;
+pub use group::FireBatchGuard;
+
+pub mod panics;
+pub use panics::{PanicHook, PanicReport};
+
+pub mod pull;
+
+pub mod state;
+pub use state::{StateInput, StateOutput};
+
+pub mod topology;
+
+pub mod graph;
+
+pub mod test;
+
+use region::{RegionBuilder, RegionCommand, RegionHandle};
 
 #[derive(Default)]
 /// The infrastructure manages regions and threads.
 pub struct InfrastructureBuilder {
     /// Region settings
     regions: Vec<Region>,
+    /// Prefix of region thread names, see `Self::with_thread_name_prefix`
+    thread_name_prefix: Option<String>,
+    /// Whether regions advance in lockstep, see `Self::with_tick_barrier`
+    tick_barrier: bool,
+    /// Whether messages are logged, see `Self::with_message_tracing`
+    message_tracing: bool,
+    /// Shared shutdown state of the infrastructure to be built
+    shutdown: Arc<Shutdown>,
+    /// Creates the region threads, see `Self::with_spawn_fn`
+    spawn_fn: Option<SpawnFn>,
+    /// Longest wait for region threads when stopping, see `Self::with_shutdown_timeout`
+    shutdown_timeout: Option<std::time::Duration>,
+    /// Topics of the infrastructure, see `Self::bus`
+    bus: Bus,
+    /// Reports panics of region threads, see `Self::with_panic_hook`
+    panic_hook: Option<panics::PanicHook>,
+    /// Whether panics of region threads also reach the previous global hook, see
+    /// `Self::with_previous_panic_hook`
+    previous_panic_hook: Option<bool>,
+    /// Common base of all ticks, see `Self::with_aligned_ticks`
+    tick_base: Option<std::time::Duration>,
+    /// Limit of buffered bytes, see `Self::with_memory_budget`
+    memory_budget: Option<u64>,
+    /// Named ports and their connections, see `Self::graph`
+    graph: graph::GraphRecord,
+    /// Format of connections without a codec, see `Self::with_default_codec`
+    #[cfg(feature = "codec")]
+    default_codec: Option<codec::Format>,
+}
+
+/// Settings of a region thread, passed to the function of `InfrastructureBuilder::with_spawn_fn`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadConfig {
+    /// Thread name, already truncated to the OS limit
+    pub name: String,
+    /// Regions executed by the thread, more than one for a shared executor
+    pub regions: Vec<String>,
+    /// Highest priority of the regions, see `RegionBuilder::with_priority`
+    pub priority: i32,
+    /// CPUs to pin the thread to, the union of those of its regions (see
+    /// `RegionBuilder::with_cpu_affinity`), empty for any CPU
+    ///
+    /// The standard library cannot pin threads, so flexcore leaves this to the spawn function,
+    /// which can use the API of the platform, e.g. `sched_setaffinity` on Linux. Without a spawn
+    /// function, a warning is logged and the thread runs on any CPU.
+    pub cpu_affinity: Vec<usize>,
+}
+
+/// Summary of what `InfrastructureBuilder::build` started, see `Infrastructure::startup_report`.
+///
+/// Logged at info level once all region threads have been spawned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupReport {
+    /// Regions whose thread has been started
+    pub regions: Vec<RegionReport>,
+    /// Number of region threads, fewer than regions if they share executors
+    pub threads: usize,
+    /// Number of nodes in all started regions
+    pub nodes: usize,
+    /// Number of open connections in the whole process, including those of other
+    /// infrastructures, as ports do not know which infrastructure they belong to
+    pub connections: usize,
+}
+
+/// Started region, see `StartupReport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionReport {
+    pub name: String,
+    pub tick: std::time::Duration,
+    pub nodes: usize,
+}
+
+impl std::fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} regions on {} threads, {} nodes, {} connections:",
+            self.regions.len(),
+            self.threads,
+            self.nodes,
+            self.connections
+        )?;
+        for region in &self.regions {
+            write!(f, " {} ({:?}, {} nodes)", region.name, region.tick, region.nodes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plan of an infrastructure that has been validated without starting it, see
+/// `InfrastructureBuilder::dry_run`.
+///
+/// Its `Display` implementation renders the plan as text, one line per region, node and port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanReport {
+    pub regions: Vec<PlanRegion>,
+}
+
+/// Region of a `PlanReport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanRegion {
+    pub name: String,
+    pub tick: std::time::Duration,
+    /// See `RegionBuilder::with_executor`
+    pub executor: Option<String>,
+    /// Nodes in execution order
+    pub nodes: Vec<PlanNode>,
+}
+
+/// Node of a `PlanRegion`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanNode {
+    pub name: String,
+    /// Named input ports, see `Node::input_port_names`
+    pub inputs: Vec<PlanPort>,
+    /// Named output ports, see `Node::output_port_names`
+    pub outputs: Vec<PlanPort>,
+}
+
+/// Named port of a `PlanNode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanPort {
+    pub name: String,
+    /// Data type of the port, see `std::any::type_name`
+    pub type_name: &'static str,
+    /// Number of connections made so far
+    pub connections: usize,
+}
+
+impl std::fmt::Display for PlanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for region in &self.regions {
+            write!(f, "region {} (tick {:?}", region.name, region.tick)?;
+            if let Some(executor) = &region.executor {
+                write!(f, ", executor {executor}")?;
+            }
+            writeln!(f, ")")?;
+            for node in &region.nodes {
+                writeln!(f, "  node {}", node.name)?;
+                for (direction, ports) in [("in", &node.inputs), ("out", &node.outputs)] {
+                    for port in ports {
+                        writeln!(
+                            f,
+                            "    {direction} {}: {} ({} connections)",
+                            port.name, port.type_name, port.connections
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates a thread running the given function, see `InfrastructureBuilder::with_spawn_fn`.
+pub type SpawnFn = Box<dyn Fn(ThreadConfig, Box<dyn FnOnce() + Send>) -> JoinHandle<()>>;
+
+/// Longest thread name accepted by the OS, in bytes (excluding the terminating null on Linux).
+const MAX_THREAD_NAME_LEN: usize = 15;
+
+/// Shorten `name` to `MAX_THREAD_NAME_LEN` at a character boundary.
+pub(crate) fn thread_name(mut name: String) -> String {
+    if name.len() > MAX_THREAD_NAME_LEN {
+        let mut end = MAX_THREAD_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    name
+}
+
+/// Align `regions` to a common epoch starting now, see `InfrastructureBuilder::with_aligned_ticks`.
+fn align_regions(regions: &mut [Region], base: std::time::Duration) -> Result<(), FlexcoreError> {
+    if let Some(region) = regions.iter().find(|r| !region::is_multiple_of(r.tick(), base)) {
+        log::error!(
+            "Tick of region {} is not a multiple of the base period {} s",
+            region.name(),
+            base.as_secs_f64()
+        );
+        return Err(FlexcoreError::InvalidTick);
+    }
+    let epoch = std::time::Instant::now();
+    for region in regions {
+        region.set_epoch(epoch);
+    }
+    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -27,9 +266,217 @@ pub enum FlexcoreError {
     NoRegions,
     /// Region has no nodes assigned.
     NoNodes,
+    /// There is no region with the given name.
+    UnknownRegion,
+    /// Tick duration is not usable.
+    InvalidTick,
+    /// There is no node with the given name in the region.
+    UnknownNode,
+    /// Node is not of the requested type.
+    NodeTypeMismatch,
+    /// Region thread is not running anymore.
+    RegionStopped,
+    /// Idle backoff settings are not usable.
+    InvalidIdleBackoff,
+    /// Node does not have a port with the given name.
+    UnknownPort,
+    /// Connected ports carry different data types, or a port does not carry the requested type.
+    PortTypeMismatch {
+        /// Data type of the output port, see `std::any::type_name`
+        output_type: &'static str,
+        /// Data type of the input port or the requested type
+        input_type: &'static str,
+    },
+    /// There is no node factory registered for the given node type.
+    UnknownNodeType,
+    /// Node names must be unique within the infrastructure.
+    DuplicateNode,
+    /// Region names must be unique within the infrastructure.
+    DuplicateRegion,
+    /// Named port has no connections, see `InfrastructureBuilder::dry_run`.
+    UnconnectedPort,
+    /// Waited longer than the given timeout, see `Infrastructure::await_first`.
+    Timeout,
+    /// Label name is not usable, see `RegionBuilder::with_labels`.
+    InvalidLabel,
 }
 
 impl InfrastructureBuilder {
+    /// Name region threads `<prefix>-<index>-<region name>` instead of just the region name.
+    ///
+    /// `index` is the position of the region in the order the regions have been added.
+    /// Thread names are truncated to the 15 bytes supported by Linux, so keep the prefix short.
+    pub fn with_thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.thread_name_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Advance all regions in lockstep: no region starts loop iteration N+1 before all regions
+    /// have finished iteration N.
+    ///
+    /// Each region thread waits for the others at the end of each iteration, so the slowest
+    /// region determines the pace of all. Regions sharing an executor (see
+    /// `RegionBuilder::with_executor`) wait together after each scheduler pass. Regions that stop,
+    /// e.g. on shutdown, leave the barrier, so the remaining ones keep running.
+    ///
+    /// `Infrastructure::pause_all` works as well: threads waiting at the barrier count as
+    /// paused, as they already finished their iteration. After `Infrastructure::resume_all`,
+    /// they continue once the others have finished theirs.
+    pub fn with_tick_barrier(mut self) -> Self {
+        self.tick_barrier = true;
+        self
+    }
+
+    /// Align the iterations of all regions to a common schedule, so data of a fast region is
+    /// fresh when a region with a harmonic, slower tick reads it.
+    ///
+    /// Iterations start at multiples of the region tick since a common epoch, taken when the
+    /// infrastructure is built, instead of one tick after the start of the previous iteration
+    /// of the region. All ticks must be multiples of `base`, e.g. a region with a tick of
+    /// 200 ms then starts its iterations together with every second one of a region with a tick
+    /// of 100 ms. An iteration that overruns its tick is followed by the next boundary instead of
+    /// an immediate iteration, and idle backoff periods (see `RegionBuilder::with_idle_backoff`)
+    /// are rounded up to a boundary.
+    ///
+    /// `Self::build` fails with `FlexcoreError::InvalidTick` if a tick is not a multiple of
+    /// `base`, as does `Infrastructure::set_region_tick` later on.
+    pub fn with_aligned_ticks(mut self, base: std::time::Duration) -> Self {
+        self.tick_base = Some(base);
+        self
+    }
+
+    /// Limit the data buffered in the connections of this infrastructure to about `bytes`, so a
+    /// consumer that cannot keep up does not exhaust the memory of the process.
+    ///
+    /// Each buffered item is estimated by the size of its type (see `std::mem::size_of`) plus a
+    /// few bytes of bookkeeping, memory the item owns on the heap is not counted. Once the
+    /// budget is exhausted, `Output::fire` drops items like a full bounded connection (see
+    /// `Output::connect_bounded`), while `Output::fire_blocking` waits for consumers to make
+    /// room. Connections to external channels (see `Output::connect_sender`) and remote
+    /// connections are not counted.
+    ///
+    /// A connection counts towards the budget of the infrastructure whose region thread sends
+    /// on it first. Data sent from other threads, e.g. by `spill::SpillOutput`, is only counted
+    /// on connections a region has sent on before. See `Infrastructure::buffered_bytes` for the
+    /// current usage. Without a budget, buffered data is not tracked at all, which saves an
+    /// atomic update for each item sent and received.
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Log every message written to or read from a connection at trace level.
+    ///
+    /// Each message is logged with its connection id and type name, and rendered with `Debug`
+    /// for ports that opted in with `Output::trace_with_debug` or `Input::trace_with_debug`,
+    /// otherwise with its size. Debugging aid for data that does not flow as expected.
+    ///
+    /// Tracing is enabled for the whole process once this infrastructure is built, also for other
+    /// infrastructures. While disabled, it costs a single atomic load per message.
+    pub fn with_message_tracing(mut self) -> Self {
+        self.message_tracing = true;
+        self
+    }
+
+    /// Create region threads with `spawn_fn` instead of `std::thread::Builder`.
+    ///
+    /// For frameworks that need threads created in a specific way, e.g. registered with a
+    /// profiler, with a NUMA policy, with an OS priority derived from `ThreadConfig::priority`, or
+    /// pinned to `ThreadConfig::cpu_affinity`.
+    /// `spawn_fn` must run the given function on a new thread and return its handle. Helper
+    /// threads, e.g. of `RegionBuilder::with_node_watchdog`, are still created by flexcore.
+    pub fn with_spawn_fn(mut self, spawn_fn: SpawnFn) -> Self {
+        self.spawn_fn = Some(spawn_fn);
+        self
+    }
+
+    /// Encode data of the network connections and spill files of this infrastructure with
+    /// `format`, unless they are given a codec of their own.
+    ///
+    /// Covers the connections made without a codec by nodes on the region threads of this
+    /// infrastructure, see `codec::default_format`. Connections made while setting up the nodes
+    /// take the format explicitly: pass `Self::default_codec` to the `*_with_codec` variants of
+    /// their methods. Other infrastructures are not affected.
+    #[cfg(feature = "codec")]
+    pub fn with_default_codec(mut self, format: codec::Format) -> Self {
+        self.default_codec = Some(format);
+        self
+    }
+
+    /// Format of the connections of this infrastructure, see `Self::with_default_codec`.
+    ///
+    /// `codec::default_format` if none has been set.
+    #[cfg(feature = "codec")]
+    pub fn default_codec(&self) -> codec::Format {
+        self.default_codec.unwrap_or_else(codec::default_format)
+    }
+
+    /// Pass panics of region threads to `hook` instead of logging them.
+    ///
+    /// Per default, a panic in a region thread is logged as error naming the thread, the region
+    /// and the node being executed, and then passed to the global panic hook, e.g. to print a
+    /// backtrace. A custom `hook` replaces both, see `Self::with_previous_panic_hook`. Applies
+    /// only to the threads of this infrastructure, panics of other threads still reach the
+    /// global hook that was set before the first infrastructure was built. A global hook set
+    /// afterwards replaces the one of flexcore, so set custom hooks first. Region threads still
+    /// exit on panics, see `ShutdownReason::RegionPanic` and `RegionExit::Panicked`.
+    pub fn with_panic_hook(mut self, hook: impl Fn(&PanicReport) + Send + Sync + 'static) -> Self {
+        self.panic_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Whether panics of region threads are passed to the global panic hook that was set before
+    /// the first infrastructure was built, after the hook of `Self::with_panic_hook`.
+    ///
+    /// Per default, they are with the default hook of flexcore and are not with a custom one.
+    pub fn with_previous_panic_hook(mut self, enabled: bool) -> Self {
+        self.previous_panic_hook = Some(enabled);
+        self
+    }
+
+    /// Stop waiting for region threads after `timeout` when shutting down or dropping the
+    /// `Infrastructure` handle.
+    ///
+    /// Threads still running then are left behind and reported as `RegionExit::TimedOut`.
+    /// Without a timeout, stopping blocks until all region threads have exited.
+    pub fn with_shutdown_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Topic bus of the infrastructure to be built, e.g. to publish and subscribe the ports of
+    /// nodes before adding them, see `bus`.
+    ///
+    /// The same bus is available from the `Infrastructure` handle while running.
+    pub fn bus(&self) -> Bus {
+        self.bus.clone()
+    }
+
+    /// Token telling nodes whether the infrastructure built from this builder is shutting down.
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        ShutdownToken(self.shutdown.clone())
+    }
+
+    /// Start region `region` in startup phase `phase`.
+    ///
+    /// Regions start in ascending order of their startup phase: a region calls `Node::on_start`
+    /// of its nodes only after all regions of lower phases have finished theirs, and begins
+    /// ticking right after. Regions of the same phase start concurrently. All regions default to
+    /// phase 0.
+    ///
+    /// `Self::build` still spawns all region threads at once and does not wait for any of them,
+    /// the threads of later phases block until it is their turn. Regions sharing an executor
+    /// (see `RegionBuilder::with_executor`) start one after another in order of their phase. A
+    /// shutdown stops waiting regions without waiting for the lower phases to start.
+    pub fn with_startup_phase(mut self, region: &str, phase: u32) -> Result<Self, FlexcoreError> {
+        let Some(r) = self.regions.iter_mut().find(|r| r.name() == region) else {
+            log::error!("There is no region {region}");
+            return Err(FlexcoreError::UnknownRegion);
+        };
+        r.set_startup_phase(phase);
+        Ok(self)
+    }
+
     /// Add a new region/thread to the infrastructure.
     ///
     /// Call `RegionBuilder::build` to finish building the region and getting back the infrastructure handle.
@@ -38,78 +485,1048 @@ impl InfrastructureBuilder {
             name: name.into(),
             tick,
             nodes: Vec::new(),
+            groups: 0,
+            idle_backoff: None,
+            strict_periodicity: false,
+            shutdown_phase: 0,
+            budget: None,
+            node_watchdog: None,
+            executor: None,
+            priority: 0,
+            cpu_affinity: Vec::new(),
+            restart_policy: RestartPolicy::Never,
+            inbound_filter: None,
+            labels: Default::default(),
+            trigger: None,
             infra: self,
         }
     }
 
-    /// Run the infrastructure
-    /// Returns a `RunningInfrastructure` handle that stops the entire system when going out of scope.
+    /// Like `Self::with_region`, but with the tick given in seconds, e.g. from a configuration file.
+    ///
+    /// Fails with `FlexcoreError::InvalidTick` if `tick_secs` is not a finite positive number or
+    /// rounds down to zero.
+    pub fn with_region_secs(self, name: impl Into<String>, tick_secs: f64) -> Result<RegionBuilder, FlexcoreError> {
+        let tick = region::tick_from_secs(tick_secs)?;
+        Ok(self.with_region(name, tick))
+    }
+
+    /// Connect the output port `from_port` of node `from_node` to the input port `to_port` of
+    /// node `to_node`.
+    ///
+    /// Nodes are looked up in all regions added so far, ports via `Node::output_port` and
+    /// `Node::input_port`. The port types are checked at runtime. Prefer `Output::connect` if
+    /// the nodes are available as concrete types.
+    pub fn connect(
+        &mut self,
+        from_node: &str,
+        from_port: &str,
+        to_node: &str,
+        to_port: &str,
+    ) -> Result<ConnectionId, FlexcoreError> {
+        self.connect_with_policy(from_node, from_port, to_node, to_port, CrossRegionPolicy::Unbounded)
+    }
+
+    /// Like `Self::connect`, with the buffering of the connection chosen by `policy`.
+    ///
+    /// With `CrossRegionPolicy::Auto`, the capacity is derived from the ticks of the regions of
+    /// both nodes, see `Self::suggested_capacity`.
+    pub fn connect_with_policy(
+        &mut self,
+        from_node: &str,
+        from_port: &str,
+        to_node: &str,
+        to_port: &str,
+        policy: CrossRegionPolicy,
+    ) -> Result<ConnectionId, FlexcoreError> {
+        let capacity = match policy {
+            CrossRegionPolicy::Unbounded => None,
+            CrossRegionPolicy::Bounded(capacity) => Some(capacity),
+            CrossRegionPolicy::Auto => Some(self.suggested_capacity(from_node, to_node)?),
+        };
+        let output = self.output_port(from_node, from_port)?;
+        let (output_type_id, output_type) = (output.item_type_id(), output.type_name());
+        let input = self.input_port(to_node, to_port)?;
+        let (input_type_id, input_type) = (input.item_type_id(), input.type_name());
+        if output_type_id != input_type_id {
+            log::error!(
+                "Cannot connect {from_node}.{from_port} of type {output_type} to {to_node}.{to_port} of type {input_type}"
+            );
+            return Err(FlexcoreError::PortTypeMismatch {
+                output_type,
+                input_type,
+            });
+        }
+        let output = self.output_port(from_node, from_port)?;
+        let link = match capacity {
+            Some(capacity) => output.link_bounded(capacity),
+            None => output.link(),
+        };
+        let id = self.input_port(to_node, to_port)?.attach(link)?;
+        self.graph.add_connection(id, (from_node, from_port), (to_node, to_port));
+        Ok(id)
+    }
+
+    /// Capacity suggested for a connection from node `from_node` to node `to_node`, based on
+    /// the ticks of their regions, see `CrossRegionPolicy::suggested_capacity`.
+    pub fn suggested_capacity(&self, from_node: &str, to_node: &str) -> Result<usize, FlexcoreError> {
+        let tick = |node: &str| {
+            self.regions
+                .iter()
+                .find(|r| r.has_node(node))
+                .map(|r| r.tick())
+                .ok_or_else(|| {
+                    log::error!("There is no node {node}");
+                    FlexcoreError::UnknownNode
+                })
+        };
+        Ok(CrossRegionPolicy::suggested_capacity(tick(from_node)?, tick(to_node)?))
+    }
+
+    fn node_mut(&mut self, name: &str) -> Result<&mut Box<dyn Node>, FlexcoreError> {
+        self.regions
+            .iter_mut()
+            .find_map(|r| r.node_mut(name))
+            .ok_or_else(|| {
+                log::error!("There is no node {name}");
+                FlexcoreError::UnknownNode
+            })
+    }
+
+    fn output_port(&mut self, node: &str, port: &str) -> Result<&mut dyn ports::AnyOutput, FlexcoreError> {
+        self.node_mut(node)?.output_port(port).ok_or_else(|| {
+            log::error!("Node {node} has no output port {port}");
+            FlexcoreError::UnknownPort
+        })
+    }
+
+    fn input_port(&mut self, node: &str, port: &str) -> Result<&mut dyn ports::AnyInput, FlexcoreError> {
+        self.node_mut(node)?.input_port(port).ok_or_else(|| {
+            log::error!("Node {node} has no input port {port}");
+            FlexcoreError::UnknownPort
+        })
+    }
+
+    /// Move all regions of `other` into this infrastructure, e.g. to compose separately built
+    /// sub-pipelines.
+    ///
+    /// Connections made within `other` are kept. Fails without changing anything if a region of
+    /// `other` has the same name as one of this infrastructure. Settings of `other` that apply to
+    /// the whole infrastructure, like `Self::with_thread_name_prefix`, are ignored. Ports using
+    /// the bus of `other` (see `Self::bus`) keep using it, its topics are not merged.
+    pub fn merge(&mut self, other: InfrastructureBuilder) -> Result<(), FlexcoreError> {
+        if let Some(region) = other
+            .regions
+            .iter()
+            .find(|o| self.regions.iter().any(|r| r.name() == o.name()))
+        {
+            log::error!("Cannot merge infrastructures, both have a region {}", region.name());
+            return Err(FlexcoreError::DuplicateRegion);
+        }
+        self.regions.extend(other.regions);
+        self.graph.merge(other.graph);
+        // Keep shutdown tokens taken from `other` working.
+        self.shutdown.link(other.shutdown);
+        Ok(())
+    }
+
+    /// Names of the regions added so far, in order of addition.
+    pub fn region_names(&self) -> Vec<String> {
+        self.regions.iter().map(|r| r.name().clone()).collect()
+    }
+
+    /// Validate the infrastructure and return its plan without starting anything.
+    ///
+    /// No threads are created and no node methods besides the port lookups are called, so
+    /// nodes do not touch their hardware. Unlike `Self::build`, which stops at the first
+    /// problem, this collects all of them:
+    ///
+    /// - `FlexcoreError::NoRegions` if there are no regions, `FlexcoreError::NoNodes` for each
+    ///   region without nodes
+    /// - `FlexcoreError::DuplicateRegion` and `FlexcoreError::DuplicateNode` for each repeated
+    ///   region or node name
+    /// - `FlexcoreError::InvalidTick` for each region whose tick is not a multiple of the base
+    ///   period of `Self::with_aligned_ticks`, like in `Self::build`. A zero tick is accepted
+    ///   otherwise, the region busy-spins.
+    /// - `FlexcoreError::UnknownPort` for each name of `Node::input_port_names` or
+    ///   `Node::output_port_names` the node does not resolve, and
+    ///   `FlexcoreError::UnconnectedPort` for each named port without connections
+    ///
+    /// Details of each problem are logged. Ports are only checked if the nodes list their names.
+    /// The builder is only borrowed mutably because `Node::input_port` and `Node::output_port`
+    /// require it, nothing is changed.
+    pub fn dry_run(&mut self) -> Result<PlanReport, Vec<FlexcoreError>> {
+        let mut errors = Vec::new();
+        if self.regions.is_empty() {
+            log::error!("Infrastructure doesn't have any regions");
+            errors.push(FlexcoreError::NoRegions);
+        }
+        let mut region_names = std::collections::HashSet::new();
+        let mut node_names = std::collections::HashSet::new();
+        let mut plan = PlanReport { regions: Vec::new() };
+        for region in &mut self.regions {
+            let name = region.name().clone();
+            if !region_names.insert(name.clone()) {
+                log::error!("There is more than one region {name}");
+                errors.push(FlexcoreError::DuplicateRegion);
+            }
+            if let Some(base) = self.tick_base.filter(|base| !region::is_multiple_of(region.tick(), *base)) {
+                log::error!("Tick of region {name} is not a multiple of the base period {} s", base.as_secs_f64());
+                errors.push(FlexcoreError::InvalidTick);
+            }
+            let mut plan_region = PlanRegion {
+                name,
+                tick: region.tick(),
+                executor: region.executor().cloned(),
+                nodes: Vec::new(),
+            };
+            for node in region.nodes_mut() {
+                let node_name = node.name().clone();
+                if !node_names.insert(node_name.clone()) {
+                    log::error!("There is more than one node {node_name}");
+                    errors.push(FlexcoreError::DuplicateNode);
+                }
+                let mut inputs = Vec::new();
+                for port in node.input_port_names() {
+                    match node.input_port(port) {
+                        Some(input) => inputs.push(PlanPort {
+                            name: port.to_string(),
+                            type_name: input.type_name(),
+                            connections: input.connection_count(),
+                        }),
+                        None => {
+                            log::error!("Node {node_name} has no input port {port}");
+                            errors.push(FlexcoreError::UnknownPort);
+                        }
+                    }
+                }
+                let mut outputs = Vec::new();
+                for port in node.output_port_names() {
+                    match node.output_port(port) {
+                        Some(output) => outputs.push(PlanPort {
+                            name: port.to_string(),
+                            type_name: output.type_name(),
+                            connections: output.connection_count(),
+                        }),
+                        None => {
+                            log::error!("Node {node_name} has no output port {port}");
+                            errors.push(FlexcoreError::UnknownPort);
+                        }
+                    }
+                }
+                for port in inputs.iter().chain(&outputs).filter(|p| p.connections == 0) {
+                    log::error!("Port {node_name}.{} is not connected", port.name);
+                    errors.push(FlexcoreError::UnconnectedPort);
+                }
+                plan_region.nodes.push(PlanNode {
+                    name: node_name,
+                    inputs,
+                    outputs,
+                });
+            }
+            if plan_region.nodes.is_empty() {
+                log::error!("Region {} has no nodes", plan_region.name);
+                errors.push(FlexcoreError::NoNodes);
+            }
+            plan.regions.push(plan_region);
+        }
+        if errors.is_empty() {
+            Ok(plan)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Run the infrastructure.
+    ///
+    /// Returns an `Infrastructure` handle that stops the entire system when going out of scope.
+    ///
+    /// # Lifecycle
+    ///
+    /// The builder is consumed: all nodes are moved into their region threads and there is no way
+    /// of getting them back. To run the same topology again, build it again, e.g. by putting its
+    /// construction into a function. Use `Infrastructure::region_names` to verify which regions
+    /// have actually been started.
     ///
     /// # Note
     ///
     /// This is non-blocking.
-    pub fn build(mut self) -> Result<Infrastructure, FlexcoreError> {
-        let regions = std::mem::take(&mut self.regions);
+    pub fn build(self) -> Result<Infrastructure, FlexcoreError> {
+        #[cfg(feature = "codec")]
+        let default_codec = self.default_codec;
+        let mut regions = self.regions;
+        let thread_name_prefix = self.thread_name_prefix;
+        let tick_barrier = self.tick_barrier;
+        let message_tracing = self.message_tracing;
+        let spawn_fn = self.spawn_fn;
+        let previous_panic_hook = self.previous_panic_hook.unwrap_or(self.panic_hook.is_none());
+        let panic_hook = self.panic_hook.unwrap_or_else(|| Arc::new(panics::log_panic));
         if regions.is_empty() {
             log::error!("Infrastructure doesn't have any regions. Add at least one using `Self::with_region`.");
             return Err(FlexcoreError::NoRegions)
         }
+        if let Some(base) = self.tick_base {
+            align_regions(&mut regions, base)?;
+        }
+        if message_tracing {
+            ports::enable_message_tracing();
+        }
+        let memory_budget = self.memory_budget.map(|bytes| Arc::new(ports::MemoryBudget::new(bytes)));
+        let groups = Arc::new(group::Groups::default());
         let mut ret = Infrastructure {
             threads: Vec::new(),
-            exit_signal: Arc::new(AtomicBool::new(false)),
+            shutdown: self.shutdown,
+            shutdown_timeout: self.shutdown_timeout,
+            regions: Vec::new(),
+            startup_report: None,
+            stalled: None,
+            bus: self.bus,
+            tick_base: self.tick_base,
+            pause: Default::default(),
+            memory_budget: memory_budget.clone(),
         };
-        for mut region in regions {
-            let exit = ret.exit_signal.clone();
-            let name = region.name().clone();
-            let builder = std::thread::Builder::new().name(region.name().clone());
-            let spawn_res = builder.spawn(move || loop {
-                let start_time = Instant::now();
-                if exit.load(Ordering::Relaxed) {
-                    return;
-                }
-                for node in region.nodes_mut() {
-                    node.tick();
-                    node.process_input();
+        let startup = Arc::new(startup::Startup::default());
+        // Regions sharing an executor run on the thread of the first of them.
+        let mut threads: Vec<(String, Vec<executor::Scheduled>, Vec<RegionHandle>)> = Vec::new();
+        let mut executors: Vec<(String, usize)> = Vec::new();
+        for (index, region) in regions.into_iter().enumerate() {
+            let (commands, command_rx) = std::sync::mpsc::channel::<RegionCommand>();
+            let handle = RegionHandle {
+                name: region.name().clone(),
+                tick: region.tick_handle(),
+                commands,
+                counters: region.counters().clone(),
+            };
+            region.attach_trigger(&ret.shutdown);
+            let scheduled = executor::Scheduled {
+                running: ret.shutdown.register(region.name().clone(), region.shutdown_phase()),
+                starting: Some(startup.register(region.startup_phase())),
+                due: std::time::Instant::now(),
+                commands: command_rx,
+                region,
+            };
+            let existing = scheduled
+                .region
+                .executor()
+                .and_then(|e| executors.iter().find(|(name, _)| name == e))
+                .map(|(_, thread)| *thread);
+            if let Some(thread) = existing {
+                threads[thread].1.push(scheduled);
+                threads[thread].2.push(handle);
+                continue;
+            }
+            let name = scheduled.region.executor().unwrap_or(scheduled.region.name()).clone();
+            if scheduled.region.executor().is_some() {
+                executors.push((name.clone(), threads.len()));
+            }
+            let full_name = match &thread_name_prefix {
+                Some(prefix) => format!("{prefix}-{index}-{name}"),
+                None => name,
+            };
+            threads.push((full_name, vec![scheduled], vec![handle]));
+        }
+        let barrier = tick_barrier.then(|| Arc::new(executor::TickBarrier::new(threads.len())));
+        ret.pause = Arc::new(executor::PauseGate::new(threads.len()));
+        for (name, scheduled, handles) in threads {
+            let shutdown = ret.shutdown.clone();
+            let startup = startup.clone();
+            let thread_barrier = barrier.clone();
+            let pause = ret.pause.clone();
+            let config = ThreadConfig {
+                name: thread_name(name.clone()),
+                regions: handles.iter().map(|h| h.name.clone()).collect(),
+                priority: scheduled.iter().map(|s| s.region.priority()).max().unwrap_or_default(),
+                cpu_affinity: scheduled
+                    .iter()
+                    .flat_map(|s| s.region.cpu_affinity())
+                    .copied()
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+            };
+            if spawn_fn.is_none() && !config.cpu_affinity.is_empty() {
+                log::warn!("Thread {} is not pinned to CPUs {:?}, this needs a spawn function", config.name, config.cpu_affinity);
+            }
+            let panic_hook = panic_hook.clone();
+            let memory_budget = memory_budget.clone();
+            let groups = groups.clone();
+            let run = move || {
+                let _hook = panics::HookGuard::set(panic_hook, previous_panic_hook);
+                ports::set_thread_memory_budget(memory_budget);
+                group::set_thread_groups(Some(groups));
+                #[cfg(feature = "codec")]
+                codec::set_thread_format(default_codec);
+                executor::run(scheduled, &startup, &shutdown, thread_barrier.as_deref(), Some(&pause))
+            };
+            let spawn_res = match &spawn_fn {
+                Some(spawn_fn) => Ok(spawn_fn(config, Box::new(run))),
+                None => std::thread::Builder::new().name(config.name).spawn(run),
+            };
+            match spawn_res {
+                Ok(join_hdl) => {
+                    ret.threads.push((handles.iter().map(|h| h.name.clone()).collect(), join_hdl));
+                    ret.regions.extend(handles);
                 }
-                let elapsed = start_time.elapsed();
-                if elapsed > region.tick() {
-                    log::warn!(
-                        "Timing in region {} exceeded by {} s",
-                        region.name(),
-                        elapsed.as_secs_f64()
-                    );
-                } else {
-                    std::thread::sleep(region.tick() - elapsed);
+                Err(e) => {
+                    log::error!("Could not start thread {}: {e}", name);
+                    if let Some(barrier) = &barrier {
+                        barrier.leave();
+                    }
+                    ret.pause.leave();
                 }
-            });
-            match spawn_res {
-                Ok(join_hdl) => ret.threads.push(join_hdl),
-                Err(e) => log::error!("Could not start thread for region {}: {e}", name),
             }
         }
+        let report = ret.create_startup_report();
+        log::info!("Started infrastructure: {report}");
+        ret.startup_report = Some(report);
         Ok(ret)
     }
+
+    /// Run all regions on the calling thread instead of spawning region threads, and block until
+    /// they have stopped.
+    ///
+    /// For single-region applications and environments where threads are unavailable or
+    /// unwanted. The regions are executed cooperatively like regions sharing an executor (see
+    /// `RegionBuilder::with_executor`): each one is stepped whenever its tick is due, by
+    /// priority and in turn. A long node method delays all regions, and a blocking one stalls
+    /// the whole infrastructure.
+    ///
+    /// Returns once all regions are finished (see `Node::is_finished`) or shutdown has been
+    /// requested through a token from `Self::shutdown_token`, e.g. by a node or a signal
+    /// handler. Thread settings like `Self::with_spawn_fn` and `Self::with_tick_barrier` do not
+    /// apply. A panicking node is reported like in region threads (see `Self::with_panic_hook`)
+    /// and unwinds out of this call.
+    pub fn run_on_current_thread(mut self) -> Result<ShutdownReason, FlexcoreError> {
+        if self.regions.is_empty() {
+            log::error!("Infrastructure doesn't have any regions. Add at least one using `Self::with_region`.");
+            return Err(FlexcoreError::NoRegions);
+        }
+        if let Some(base) = self.tick_base {
+            align_regions(&mut self.regions, base)?;
+        }
+        if self.message_tracing {
+            ports::enable_message_tracing();
+        }
+        let memory_budget = self.memory_budget.map(|bytes| Arc::new(ports::MemoryBudget::new(bytes)));
+        let startup = Arc::new(startup::Startup::default());
+        let scheduled = self
+            .regions
+            .into_iter()
+            .map(|region| {
+                region.attach_trigger(&self.shutdown);
+                executor::Scheduled {
+                    running: self.shutdown.register(region.name().clone(), region.shutdown_phase()),
+                    starting: Some(startup.register(region.startup_phase())),
+                    due: std::time::Instant::now(),
+                    // Nobody sends commands without an `Infrastructure` handle.
+                    commands: std::sync::mpsc::channel().1,
+                    region,
+                }
+            })
+            .collect();
+        let previous_panic_hook = self.previous_panic_hook.unwrap_or(self.panic_hook.is_none());
+        let panic_hook = self.panic_hook.unwrap_or_else(|| Arc::new(panics::log_panic));
+        let _hook = panics::HookGuard::set(panic_hook, previous_panic_hook);
+        ports::set_thread_memory_budget(memory_budget);
+        group::set_thread_groups(Some(Arc::default()));
+        #[cfg(feature = "codec")]
+        codec::set_thread_format(self.default_codec);
+        executor::run(scheduled, &startup, &self.shutdown, None, None);
+        ports::set_thread_memory_budget(None);
+        group::set_thread_groups(None);
+        #[cfg(feature = "codec")]
+        codec::set_thread_format(None);
+        self.shutdown.set_reason(ShutdownReason::Finished);
+        Ok(self.shutdown.reason().unwrap_or(ShutdownReason::Finished))
+    }
 }
 
+/// Handle to running regions.
+///
 /// Regions/threads are exited once this handle goes out of scope.
 ///
-/// The only way of creating this object shall be `Infrastructure::run`.
+/// The only way of creating this object shall be `InfrastructureBuilder::build`.
 pub struct Infrastructure {
-    /// Thread handles after thread processing has been started
-    threads: Vec<JoinHandle<()>>,
-    /// Shared exit signal to stop threads
-    exit_signal: Arc<AtomicBool>,
+    /// Thread handles after thread processing has been started, with the regions they execute
+    threads: Vec<(Vec<String>, JoinHandle<()>)>,
+    /// Shared shutdown state to stop threads
+    shutdown: Arc<Shutdown>,
+    /// See `InfrastructureBuilder::with_shutdown_timeout`
+    shutdown_timeout: Option<std::time::Duration>,
+    /// Set once all threads have been spawned, see `Self::startup_report`
+    startup_report: Option<StartupReport>,
+    /// See `InfrastructureBuilder::bus`
+    bus: Bus,
+    /// See `InfrastructureBuilder::with_aligned_ticks`
+    tick_base: Option<std::time::Duration>,
+    /// Holds the region threads, see `Self::pause_all`
+    pause: Arc<executor::PauseGate>,
+    /// Access to all regions whose threads have been started
+    regions: Vec<RegionHandle>,
+    /// Regions without progress, set by the supervisor thread, see `Self::enable_supervisor`
+    stalled: Option<Arc<std::sync::Mutex<Vec<String>>>>,
+    /// See `InfrastructureBuilder::with_memory_budget`
+    memory_budget: Option<Arc<ports::MemoryBudget>>,
 }
 
-impl Drop for Infrastructure {
-    fn drop(&mut self) {
-        self.exit_signal.swap(true, Ordering::Relaxed);
-        let threads = std::mem::take(&mut self.threads);
-        for thr in threads {
+impl Infrastructure {
+    /// Names of all regions whose threads have been started.
+    ///
+    /// Regions whose thread could not be spawned are missing here.
+    pub fn region_names(&self) -> Vec<String> {
+        self.regions.iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// Summary of the regions, threads, nodes and connections that have been started.
+    ///
+    /// Reflects the state right after `InfrastructureBuilder::build`, not later changes like
+    /// `Self::add_node`.
+    pub fn startup_report(&self) -> &StartupReport {
+        self.startup_report.as_ref().expect("set by InfrastructureBuilder::build")
+    }
+
+    fn create_startup_report(&self) -> StartupReport {
+        let regions: Vec<RegionReport> = self
+            .regions
+            .iter()
+            .map(|r| RegionReport {
+                name: r.name.clone(),
+                tick: std::time::Duration::from_nanos(r.tick.load(Ordering::Relaxed)),
+                nodes: r.counters.nodes.lock().unwrap_or_else(|e| e.into_inner()).len(),
+            })
+            .collect();
+        StartupReport {
+            threads: self.threads.len(),
+            nodes: regions.iter().map(|r| r.nodes).sum(),
+            connections: ports::open_connection_count(),
+            regions,
+        }
+    }
+
+    /// Topic bus of this infrastructure, see `InfrastructureBuilder::bus`.
+    ///
+    /// Subscriptions made while running receive everything published from then on.
+    pub fn bus(&self) -> Bus {
+        self.bus.clone()
+    }
+
+    /// Token telling whether this infrastructure is shutting down.
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        ShutdownToken(self.shutdown.clone())
+    }
+
+    /// Whether all regions have stopped, e.g. because all their nodes are finished.
+    ///
+    /// See `Region` for when a region finishes on its own.
+    pub fn is_finished(&self) -> bool {
+        self.threads.iter().all(|(_, t)| t.is_finished())
+    }
+
+    /// Whether any region is still running.
+    ///
+    /// Cheap and non-blocking, e.g. for a supervising loop deciding whether to restart.
+    pub fn is_running(&self) -> bool {
+        !self.is_finished()
+    }
+
+    /// Names of the regions that have stopped, e.g. because all their nodes are finished or
+    /// their thread panicked.
+    pub fn finished_regions(&self) -> Vec<String> {
+        self.regions
+            .iter()
+            .filter(|r| r.counters.stopped.load(Ordering::Relaxed))
+            .map(|r| r.name.clone())
+            .collect()
+    }
+
+    /// Watch all regions for stalls in a background thread.
+    ///
+    /// Every `check_interval`, the supervisor checks whether each running region has finished a
+    /// loop iteration since the previous check. A region without progress for longer than
+    /// `stall_threshold` is logged as warning and listed by `Self::stalled_regions` until it
+    /// finishes an iteration again. Choose a threshold well above the tick of the slowest region,
+    /// including idle backoff. Triggered regions (see `LoopMode::Triggered`) waiting for their
+    /// trigger are not reported. To find the node a region is stuck in, see
+    /// `RegionBuilder::with_node_watchdog`.
+    ///
+    /// Regions with `RestartPolicy::Always` that panicked are restarted by the supervisor, with
+    /// a delay doubling for each restart in a row from 100 ms up to a minute, checked every
+    /// `check_interval`. A region that runs for a minute without panicking is restarted without
+    /// delay again. Stalled regions are only reported, as region threads cannot be interrupted.
+    /// Restart the whole infrastructure if needed, e.g. once `Self::stalled_regions` is not
+    /// empty, see `Self::reload`. Has no effect if the supervisor is already enabled.
+    pub fn enable_supervisor(
+        &mut self,
+        check_interval: std::time::Duration,
+        stall_threshold: std::time::Duration,
+    ) {
+        if self.stalled.is_some() {
+            log::warn!("Supervisor is already enabled");
+            return;
+        }
+        let stalled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let regions = self.regions.iter().map(|r| r.counters.clone()).collect();
+        let shutdown = self.shutdown.clone();
+        let thread_stalled = stalled.clone();
+        let interval = check_interval.max(std::time::Duration::from_millis(1));
+        let spawn_res = std::thread::Builder::new()
+            .name(thread_name("flexcore-supervisor".into()))
+            .spawn(move || supervisor::watch(regions, &shutdown, interval, stall_threshold, &thread_stalled));
+        match spawn_res {
+            Ok(_) => self.stalled = Some(stalled),
+            Err(e) => log::error!("Could not start supervisor thread: {e}"),
+        }
+    }
+
+    /// Names of the regions the supervisor currently considers stalled, see
+    /// `Self::enable_supervisor`. Empty if the supervisor is not enabled.
+    pub fn stalled_regions(&self) -> Vec<String> {
+        self.stalled
+            .as_ref()
+            .map(|s| s.lock().unwrap_or_else(|e| e.into_inner()).clone())
+            .unwrap_or_default()
+    }
+
+    /// Block until all regions have stopped on their own, then return why.
+    ///
+    /// Used to run finite pipelines to completion, or to wait for a node requesting shutdown
+    /// (see `ShutdownToken::request_by_node`). Never returns if a region contains a node that
+    /// is never finished (see `Node::is_finished`) and nobody requests shutdown.
+    pub fn wait(mut self) -> ShutdownReason {
+        self.join(None);
+        self.shutdown.set_reason(ShutdownReason::Finished);
+        self.shutdown.reason().unwrap_or(ShutdownReason::Finished)
+    }
+
+    /// Stop all regions, wait for them and report why the infrastructure stopped and how each
+    /// region exited.
+    ///
+    /// Same as dropping the handle, except for the returned report. Its reason is
+    /// `ShutdownReason::Requested` unless something else stopped the infrastructure before.
+    /// Regions sharing a thread (see `RegionBuilder::with_executor`) share their exit status.
+    pub fn shutdown(mut self) -> ShutdownReport {
+        self.shutdown.request(ShutdownReason::Requested);
+        let deadline = self.shutdown_timeout.map(|t| std::time::Instant::now() + t);
+        let regions = self.join(deadline);
+        ShutdownReport {
+            reason: self.shutdown.reason().unwrap_or(ShutdownReason::Requested),
+            regions,
+        }
+    }
+
+    /// Replace this infrastructure with the one of `builder`, carrying over the state of the
+    /// nodes that exist in both, e.g. to apply a changed configuration file.
+    ///
+    /// All regions are paused like by `Self::pause_all`, so the snapshots of their nodes (see
+    /// `Node::snapshot`) are taken at a common point, between loop iterations. Then all regions
+    /// are stopped like by `Self::shutdown`, without running further iterations, except for
+    /// the final one of regions with a shutdown phase (see `RegionBuilder::with_shutdown_phase`).
+    /// The new topology is restored from the snapshots of nodes with the same region and node
+    /// name (see `Node::restore`) and started.
+    ///
+    /// Only the state of the nodes is carried over: items still buffered in the old connections
+    /// are lost, so the new nodes may miss data the old ones had sent but not yet received.
+    /// Regions that have stopped before are skipped.
+    pub fn reload(self, mut builder: InfrastructureBuilder) -> Result<Infrastructure, FlexcoreError> {
+        self.pause.pause(&self.shutdown);
+        let mut snapshots: HashMap<(String, String), Vec<u8>> = HashMap::new();
+        for region in self.region_names() {
+            let states = self.run_in_region(&region, |r| {
+                r.nodes_mut()
+                    .filter_map(|n| n.snapshot().map(|state| (n.name().clone(), state)))
+                    .collect::<Vec<_>>()
+            });
+            match states {
+                Ok(states) => {
+                    snapshots.extend(states.into_iter().map(|(node, state)| ((region.clone(), node), state)));
+                }
+                Err(_) => log::warn!("Region {region} stopped before its snapshot"),
+            }
+        }
+        let report = self.shutdown();
+        log::info!("Stopped infrastructure for reload: {:?}", report.reason);
+        for region in &mut builder.regions {
+            let region_name = region.name().clone();
+            for node in region.nodes_mut() {
+                if let Some(state) = snapshots.get(&(region_name.clone(), node.name().clone())) {
+                    node.restore(state);
+                }
+            }
+        }
+        builder.build()
+    }
+
+    /// Join all region threads, giving up on those still running at `deadline`.
+    fn join(&mut self, deadline: Option<std::time::Instant>) -> HashMap<String, RegionExit> {
+        let mut ret = HashMap::new();
+        for (regions, thr) in std::mem::take(&mut self.threads) {
             let name: String = thr.thread().name().unwrap_or_default().into();
-            if thr.join().is_err() {
-                log::warn!("Cannot join thread {}", name);
+            if let Some(deadline) = deadline {
+                while !thr.is_finished() && std::time::Instant::now() < deadline {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+            }
+            let exit = if !thr.is_finished() && deadline.is_some() {
+                log::warn!("Thread {name} did not stop in time");
+                RegionExit::TimedOut
+            } else {
+                match thr.join() {
+                    Ok(()) => RegionExit::Ok,
+                    Err(payload) => {
+                        log::warn!("Cannot join thread {}", name);
+                        RegionExit::Panicked(shutdown::panic_message(payload.as_ref()))
+                    }
+                }
+            };
+            ret.extend(regions.into_iter().map(|r| (r, exit.clone())));
+        }
+        ret
+    }
+
+    /// Current statistics of all running regions and their nodes.
+    ///
+    /// This does not block the region threads.
+    pub fn metrics(&self) -> Vec<metrics::RegionMetrics> {
+        self.regions.iter().map(|r| r.counters.snapshot()).collect()
+    }
+
+    /// Current statistics in the Prometheus text exposition format.
+    ///
+    /// Thin formatter on top of `Self::metrics`, e.g. to be served by an HTTP handler.
+    #[cfg(feature = "prometheus")]
+    pub fn metrics_text(&self) -> String {
+        let mut text = metrics::prometheus::format(&self.metrics());
+        text.push_str(&metrics::prometheus::format_buffered_bytes(self.buffered_bytes()));
+        text
+    }
+
+    /// Freeze all regions at a consistent point, e.g. to inspect the state of the whole system
+    /// while debugging, until `Self::resume_all`.
+    ///
+    /// Iterations in progress are finished, including all their nodes: each region thread stops
+    /// before its next scheduler pass, so regions sharing an executor (see
+    /// `RegionBuilder::with_executor`) finish all iterations due in the current pass. Blocks
+    /// until all region threads have stopped, which takes up to the longest iteration in
+    /// progress. Data sent meanwhile stays buffered in the connections. Calls that run in a
+    /// region, like `Self::with_node`, are still executed, so the frozen state can be inspected.
+    ///
+    /// With `InfrastructureBuilder::with_tick_barrier`, threads that finished their pass and
+    /// wait at the barrier for a paused thread count as paused, so they do not block this call.
+    ///
+    /// Requesting shutdown, also by dropping this handle, ends the pause. Time spent paused
+    /// counts towards the next iteration (see `node::TickContext::dt`) and is reported by the
+    /// supervisor (see `Self::enable_supervisor`) like a stall.
+    pub fn pause_all(&self) {
+        self.pause.pause(&self.shutdown);
+        log::info!("Paused all regions");
+    }
+
+    /// Let all regions continue after `Self::pause_all`.
+    ///
+    /// Regions whose iteration became due while paused run it right away.
+    pub fn resume_all(&self) {
+        self.pause.resume();
+        log::info!("Resumed all regions");
+    }
+
+    /// Whether the regions are paused, see `Self::pause_all`.
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// Estimated bytes currently buffered in the connections of this infrastructure, see
+    /// `InfrastructureBuilder::with_memory_budget`. Always 0 without a budget.
+    pub fn buffered_bytes(&self) -> u64 {
+        self.memory_budget.as_ref().map_or(0, |b| b.buffered_bytes())
+    }
+
+    /// Change the tick duration of a running region.
+    ///
+    /// The new tick takes effect with the next iteration of the region loop. A zero tick lets
+    /// the region busy-spin, like at build time. With `InfrastructureBuilder::with_aligned_ticks`,
+    /// it must be a non-zero multiple of the base period.
+    pub fn set_region_tick(&self, name: &str, tick: std::time::Duration) -> Result<(), FlexcoreError> {
+        if let Some(base) = self.tick_base.filter(|base| !region::is_multiple_of(tick, *base)) {
+            log::error!("Tick of region {name} must be a multiple of {} s", base.as_secs_f64());
+            return Err(FlexcoreError::InvalidTick);
+        }
+        let region = self.region(name)?;
+        region.tick.store(region::duration_to_nanos(tick), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Run `f` on the node `node` of region `region`, e.g. to inspect its state in tests.
+    ///
+    /// `f` is executed by the region thread before the next iteration of the region loop, so
+    /// this blocks for up to one tick of the region. Fails with `FlexcoreError::NodeTypeMismatch`
+    /// if the node is not of type `T`.
+    pub fn with_node<T: Node + 'static, R: Send + 'static>(
+        &self,
+        region: &str,
+        node: &str,
+        f: impl FnOnce(&mut T) -> R + Send + 'static,
+    ) -> Result<R, FlexcoreError> {
+        let node = node.to_string();
+        self.run_in_region(region, move |region| {
+            match region.node_mut(&node) {
+                None => {
+                    log::error!("Region {} has no node {node}", region.name());
+                    Err(FlexcoreError::UnknownNode)
+                }
+                Some(n) => match AsAny::as_any_mut(n.as_mut()).downcast_mut::<T>() {
+                    None => {
+                        log::error!(
+                            "Node {node} in region {} is not of type {}",
+                            region.name(),
+                            std::any::type_name::<T>()
+                        );
+                        Err(FlexcoreError::NodeTypeMismatch)
+                    }
+                    Some(n) => Ok(f(n)),
+                },
+            }
+        })?
+    }
+
+    /// Pass `config` to the node `node` of region `region`, see `Reconfigurable`.
+    ///
+    /// `Reconfigurable::reconfigure` is called by the region thread before the next iteration of the
+    /// region loop, so this blocks for up to one tick of the region. Fails with
+    /// `FlexcoreError::NodeTypeMismatch` if the node is not of type `T`.
+    pub fn reconfigure<T: Reconfigurable + 'static>(
+        &self,
+        region: &str,
+        node: &str,
+        config: T::Config,
+    ) -> Result<(), FlexcoreError> {
+        self.with_node(region, node, move |n: &mut T| n.reconfigure(config))
+    }
+
+    /// Observe the output port `port` of node `node` in region `region`, see `Output::subscribe`.
+    ///
+    /// The observer is attached by the region thread before the next iteration of the region
+    /// loop, so this blocks for up to one tick of the region.
+    pub fn subscribe<T: Send + 'static>(
+        &self,
+        region: &str,
+        node: &str,
+        port: &str,
+    ) -> Result<std::sync::mpsc::Receiver<T>, FlexcoreError> {
+        let node = node.to_string();
+        let port = port.to_string();
+        self.run_in_region(region, move |region| {
+            let Some(n) = region.node_mut(&node) else {
+                log::error!("Region {} has no node {node}", region.name());
+                return Err(FlexcoreError::UnknownNode);
+            };
+            let Some(output) = n.output_port(&port) else {
+                log::error!("Node {node} has no output port {port}");
+                return Err(FlexcoreError::UnknownPort);
+            };
+            if output.item_type_id() != std::any::TypeId::of::<T>() {
+                log::error!(
+                    "Output port {node}.{port} carries {}, not {}",
+                    output.type_name(),
+                    std::any::type_name::<T>()
+                );
+                return Err(FlexcoreError::PortTypeMismatch {
+                    output_type: output.type_name(),
+                    input_type: std::any::type_name::<T>(),
+                });
+            }
+            let output_type = output.type_name();
+            output
+                .observe()
+                .downcast::<std::sync::mpsc::Receiver<T>>()
+                .map(|rx| *rx)
+                .map_err(|_| FlexcoreError::PortTypeMismatch {
+                    output_type,
+                    input_type: std::any::type_name::<T>(),
+                })
+        })?
+    }
+
+    /// Block until the output port `port` of node `node` in region `region` fires, and return
+    /// the item.
+    ///
+    /// For startup synchronization, e.g. to wait until a producer delivers its first real value
+    /// before enabling its consumers. Only items fired after the port has been subscribed to (see
+    /// `Self::subscribe`) count, which blocks for up to one tick of the region. Fails with
+    /// `FlexcoreError::Timeout` if nothing is fired within `timeout` from the call, and with
+    /// `FlexcoreError::RegionStopped` if the port is dropped before.
+    pub fn await_first<T: Send + 'static>(
+        &self,
+        region: &str,
+        node: &str,
+        port: &str,
+        timeout: std::time::Duration,
+    ) -> Result<T, FlexcoreError> {
+        let deadline = std::time::Instant::now() + timeout;
+        let rx = self.subscribe::<T>(region, node, port)?;
+        match rx.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now())) {
+            Ok(t) => Ok(t),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                log::error!("{node}.{port} in region {region} did not fire within {} s", timeout.as_secs_f64());
+                Err(FlexcoreError::Timeout)
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                log::error!("{node}.{port} in region {region} has been dropped before firing");
+                Err(FlexcoreError::RegionStopped)
             }
         }
     }
+
+    /// Connect the output port `from_port` of node `from_node` in region `from_region` to the
+    /// input port `to_port` of node `to_node` in region `to_region` while running.
+    ///
+    /// Like `InfrastructureBuilder::connect`, but for nodes that are already running, e.g. to
+    /// connect a port the node created at runtime (see `ports::DynamicOutputs`). Ports are looked
+    /// up by the region threads, so this blocks for up to one tick of each region involved.
+    pub fn connect(
+        &self,
+        from_region: &str,
+        from_node: &str,
+        from_port: &str,
+        to_region: &str,
+        to_node: &str,
+        to_port: &str,
+    ) -> Result<ConnectionId, FlexcoreError> {
+        let (node, port) = (to_node.to_string(), to_port.to_string());
+        let input_type = self.run_in_region(to_region, move |region| {
+            let input = Self::region_input_port(region, &node, &port)?;
+            Ok((input.item_type_id(), input.type_name()))
+        })??;
+        let (node, port) = (from_node.to_string(), from_port.to_string());
+        let link = self.run_in_region(from_region, move |region| {
+            let Some(n) = region.node_mut(&node) else {
+                log::error!("Region {} has no node {node}", region.name());
+                return Err(FlexcoreError::UnknownNode);
+            };
+            let Some(output) = n.output_port(&port) else {
+                log::error!("Node {node} has no output port {port}");
+                return Err(FlexcoreError::UnknownPort);
+            };
+            if output.item_type_id() != input_type.0 {
+                log::error!(
+                    "Cannot connect {node}.{port} of type {} to port of type {}",
+                    output.type_name(),
+                    input_type.1
+                );
+                return Err(FlexcoreError::PortTypeMismatch {
+                    output_type: output.type_name(),
+                    input_type: input_type.1,
+                });
+            }
+            Ok(output.link())
+        })??;
+        let (node, port) = (to_node.to_string(), to_port.to_string());
+        self.run_in_region(to_region, move |region| {
+            Self::region_input_port(region, &node, &port)?.attach(link)
+        })?
+    }
+
+    /// Names of the nodes currently in region `region`, in execution order (see `Region`).
+    ///
+    /// Reflects nodes added or removed at runtime. This does not block the region thread.
+    pub fn node_names(&self, region: &str) -> Result<Vec<String>, FlexcoreError> {
+        let region = self.region(region)?;
+        let nodes = region.counters.nodes.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(nodes.iter().map(|n| n.name.clone()).collect())
+    }
+
+    /// Add `node` to the running region `region` with priority 0.
+    ///
+    /// The node is started (see `Node::on_start`) and inserted into the execution order (see
+    /// `Region`) by the region thread before the next iteration of the region loop, so this
+    /// blocks for up to one tick of the region.
+    pub fn add_node<T: Node + 'static>(&self, region: &str, node: T) -> Result<(), FlexcoreError> {
+        self.add_prioritized_node(region, Box::new(node), 0)
+    }
+
+    /// Like `Self::add_node`, with the execution priority of the node, see
+    /// `RegionBuilder::with_prioritized_node`.
+    pub fn add_prioritized_node(&self, region: &str, node: Box<dyn Node>, priority: i32) -> Result<(), FlexcoreError> {
+        self.run_in_region(region, move |r| {
+            r.add_node(node, priority).map_err(|node| {
+                log::error!("Region {} already has a node {}", r.name(), node.name());
+                FlexcoreError::DuplicateNode
+            })
+        })?
+    }
+
+    /// Remove the node `node` from the running region `region` and drop it.
+    ///
+    /// Dropping the node closes all connections of its ports. The peer ports remove these
+    /// connections the next time they use them.
+    ///
+    /// The node is removed by the region thread before the next iteration of the region loop,
+    /// so this blocks for up to one tick of the region.
+    pub fn remove_node(&self, region: &str, node: &str) -> Result<(), FlexcoreError> {
+        let node = node.to_string();
+        self.run_in_region(region, move |r| match r.remove_node(&node) {
+            Some(_) => {
+                log::info!("Removed node {node} from region {}", r.name());
+                Ok(())
+            }
+            None => {
+                log::error!("Region {} has no node {node}", r.name());
+                Err(FlexcoreError::UnknownNode)
+            }
+        })?
+    }
+
+    /// Run `f` in the thread of region `region` before its next loop iteration and wait for its result.
+    fn run_in_region<R: Send + 'static>(
+        &self,
+        region: &str,
+        f: impl FnOnce(&mut Region) -> R + Send + 'static,
+    ) -> Result<R, FlexcoreError> {
+        let handle = self.region(region)?;
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+        let command: RegionCommand = Box::new(move |region| {
+            // The caller is blocked on the result, nothing to do if it vanished.
+            let _ = result_tx.send(f(region));
+        });
+        if handle.commands.send(command).is_err() {
+            log::error!("Region {region} is not running");
+            return Err(FlexcoreError::RegionStopped);
+        }
+        // Wake triggered regions waiting for their trigger.
+        self.shutdown.notify_change();
+        result_rx.recv().map_err(|_| {
+            log::error!("Region {region} stopped before executing command");
+            FlexcoreError::RegionStopped
+        })
+    }
+
+    fn region_input_port<'a>(
+        region: &'a mut Region,
+        node: &str,
+        port: &str,
+    ) -> Result<&'a mut dyn ports::AnyInput, FlexcoreError> {
+        let name = region.name().clone();
+        let Some(n) = region.node_mut(node) else {
+            log::error!("Region {name} has no node {node}");
+            return Err(FlexcoreError::UnknownNode);
+        };
+        n.input_port(port).ok_or_else(|| {
+            log::error!("Node {node} has no input port {port}");
+            FlexcoreError::UnknownPort
+        })
+    }
+
+    fn region(&self, name: &str) -> Result<&RegionHandle, FlexcoreError> {
+        self.regions.iter().find(|r| r.name == name).ok_or_else(|| {
+            log::error!("There is no region {name}");
+            FlexcoreError::UnknownRegion
+        })
+    }
+}
+
+impl Drop for Infrastructure {
+    fn drop(&mut self) {
+        self.shutdown.request(ShutdownReason::Requested);
+        let deadline = self.shutdown_timeout.map(|t| std::time::Instant::now() + t);
+        self.join(deadline);
+    }
 }
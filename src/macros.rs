@@ -0,0 +1,142 @@
+/// Declare the ports of a node as a struct.
+///
+/// Expands to a struct with one public `Input` field per `in` entry and one public `Output`
+/// field per `out` entry, a `Default` implementation that creates all ports unconnected, and the
+/// associated constants `INPUTS` and `OUTPUTS` listing the port names in declaration order.
+///
+/// The struct also gets the methods `input_port` and `output_port` looking up ports by their
+/// field name. Forward `Node::input_port` and `Node::output_port` to them to make the ports
+/// connectable by name, and return `INPUTS` and `OUTPUTS` from `Node::input_port_names` and
+/// `Node::output_port_names` to have them checked by `InfrastructureBuilder::dry_run`.
+///
+/// # Input order
+///
+/// Optionally, name an item type after the ports with `items: Name`. The macro then generates
+/// an enum `Name` with one variant per input port, named like the port and holding its data
+/// type, and a method `fetch_ordered` returning the data of all input ports as one `Vec<Name>`.
+/// Ports are drained in declaration order: within one call, all items of a port come before any
+/// item of a port declared after it. Declare control ports like `reset` or `config` first to
+/// have them applied before bulk data of the same tick. If the node limits its per-tick
+/// consumption (see `Node::max_items_per_tick`), ports declared first get the budget first.
+///
+/// The generated struct is meant to be embedded in a node. Nodes that create their ports by hand
+/// work exactly the same, both kinds can be connected to each other.
+///
+/// ```ignore
+/// ports! {
+///     pub struct ProcessingPorts {
+///         in: [config: Config, measurements: SensorData],
+///         out: [velocity: f64],
+///         items: ProcessingInput,
+///     }
+/// }
+///
+/// for item in self.ports.fetch_ordered() {
+///     match item {
+///         ProcessingInput::config(config) => self.config = config,
+///         ProcessingInput::measurements(data) => self.process(data),
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! ports {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            in: [$($in_name:ident: $in_ty:ty),* $(,)?],
+            out: [$($out_name:ident: $out_ty:ty),* $(,)?],
+            items: $items:ident $(,)?
+        }
+    ) => {
+        $crate::ports! {
+            $(#[$meta])*
+            $vis struct $name {
+                in: [$($in_name: $in_ty),*],
+                out: [$($out_name: $out_ty),*],
+            }
+        }
+
+        /// Data received on one of the input ports of
+        #[doc = concat!("`", stringify!($name), "`.")]
+        #[allow(non_camel_case_types, dead_code)]
+        $vis enum $items {
+            $($in_name($in_ty),)*
+        }
+
+        impl $name {
+            /// Read all data that is currently buffered on the input ports, in declaration order
+            /// of the ports.
+            #[allow(dead_code)]
+            pub fn fetch_ordered(&mut self) -> Vec<$items> {
+                let mut ret = Vec::new();
+                $(ret.extend(self.$in_name.fetch().into_iter().map($items::$in_name));)*
+                ret
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            in: [$($in_name:ident: $in_ty:ty),* $(,)?],
+            out: [$($out_name:ident: $out_ty:ty),* $(,)?] $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(pub $in_name: $crate::Input<$in_ty>,)*
+            $(pub $out_name: $crate::Output<$out_ty>,)*
+        }
+
+        impl $name {
+            /// Names of the input ports in declaration order.
+            #[allow(dead_code)]
+            pub const INPUTS: &'static [&'static str] = &[$(stringify!($in_name)),*];
+            /// Names of the output ports in declaration order.
+            #[allow(dead_code)]
+            pub const OUTPUTS: &'static [&'static str] = &[$(stringify!($out_name)),*];
+
+            /// The input port called `name`.
+            #[allow(dead_code)]
+            pub fn input_port(&mut self, name: &str) -> Option<&mut dyn $crate::ports::AnyInput> {
+                match name {
+                    $(stringify!($in_name) => Some(&mut self.$in_name),)*
+                    _ => None,
+                }
+            }
+
+            /// The output port called `name`.
+            #[allow(dead_code)]
+            pub fn output_port(&mut self, name: &str) -> Option<&mut dyn $crate::ports::AnyOutput> {
+                match name {
+                    $(stringify!($out_name) => Some(&mut self.$out_name),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl ::core::default::Default for $name {
+            fn default() -> Self {
+                Self {
+                    $($in_name: $crate::Input::default(),)*
+                    $($out_name: $crate::Output::default(),)*
+                }
+            }
+        }
+    };
+}
+
+/// Fire values on several output ports as one group, see `FireBatchGuard`.
+///
+/// Each `port => value` pair calls `fire` on the port. Values are evaluated within the group,
+/// so compute expensive ones beforehand.
+///
+/// ```ignore
+/// fire_group!(self.out_position => position, self.out_velocity => velocity);
+/// ```
+#[macro_export]
+macro_rules! fire_group {
+    ($($port:expr => $value:expr),* $(,)?) => {{
+        let _group = $crate::FireBatchGuard::new();
+        $($port.fire($value);)*
+    }};
+}
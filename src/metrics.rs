@@ -0,0 +1,198 @@
+//! Runtime statistics of regions and nodes.
+//!
+//! Counters are updated by the region threads and can be read at any time without blocking them,
+//! see `Infrastructure::metrics`. All counters are cumulative since start, so throughput over a
+//! window is the difference of two snapshots divided by the time between them.
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::region::{RegionTrigger, RestartPolicy};
+
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+/// Counters of a single node, updated by its region thread.
+#[derive(Debug, Default)]
+pub(crate) struct NodeCounters {
+    pub(crate) name: String,
+    /// Items received on all `Input` ports of the node
+    pub(crate) received: AtomicU64,
+    /// Calls to `Output::fire` on all output ports of the node
+    pub(crate) fired: AtomicU64,
+    /// Sequence gaps detected on all `Input` ports of the node
+    pub(crate) gaps: AtomicU64,
+    /// Nanoseconds spent sending and receiving on all ports of the node
+    #[cfg(feature = "contention")]
+    pub(crate) channel_nanos: [AtomicU64; 2],
+}
+
+impl NodeCounters {
+    pub(crate) fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(&self) -> NodeMetrics {
+        NodeMetrics {
+            name: self.name.clone(),
+            received: self.received.load(Ordering::Relaxed),
+            fired: self.fired.load(Ordering::Relaxed),
+            gaps: self.gaps.load(Ordering::Relaxed),
+            #[cfg(feature = "contention")]
+            send_time: std::time::Duration::from_nanos(self.channel_nanos[0].load(Ordering::Relaxed)),
+            #[cfg(feature = "contention")]
+            recv_time: std::time::Duration::from_nanos(self.channel_nanos[1].load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters of a region, updated by its region thread.
+#[derive(Debug, Default)]
+pub(crate) struct RegionCounters {
+    pub(crate) name: String,
+    /// See `RegionBuilder::with_labels`
+    pub(crate) labels: BTreeMap<String, String>,
+    /// Finished iterations of the region loop
+    pub(crate) iterations: AtomicU64,
+    /// Iterations that took longer than the tick
+    pub(crate) overruns: AtomicU64,
+    /// Node calls reported by the node watchdog
+    pub(crate) stalls: AtomicU64,
+    /// Deviations of the loop period from the scheduled one, see `Self::record_jitter`
+    jitter_samples: AtomicU64,
+    /// Sum of the deviations in seconds, as `f64` bits
+    jitter_sum: AtomicU64,
+    /// Sum of the squared deviations in seconds, as `f64` bits
+    jitter_sq_sum: AtomicU64,
+    /// Largest absolute deviation in nanoseconds
+    max_jitter: AtomicU64,
+    /// Counters of all nodes currently in the region
+    pub(crate) nodes: Mutex<Vec<Arc<NodeCounters>>>,
+    /// Whether the region has stopped and its nodes have been dropped
+    pub(crate) stopped: AtomicBool,
+    /// Trigger of a region waiting for it, see `LoopMode::Triggered`
+    pub(crate) trigger: Option<Arc<RegionTrigger>>,
+    /// See `RegionBuilder::with_restart_policy`
+    pub(crate) restart_policy: RestartPolicy,
+    /// Message of the panic the region waits to be restarted after
+    pub(crate) failure: Mutex<Option<String>>,
+    /// Set by the supervisor to restart a failed region
+    pub(crate) restart_requested: AtomicBool,
+    /// Restarts after panics
+    pub(crate) restarts: AtomicU64,
+}
+
+impl RegionCounters {
+    pub(crate) fn new(
+        name: String,
+        labels: BTreeMap<String, String>,
+        nodes: Vec<Arc<NodeCounters>>,
+        trigger: Option<Arc<RegionTrigger>>,
+        restart_policy: RestartPolicy,
+    ) -> Self {
+        Self {
+            name,
+            labels,
+            nodes: Mutex::new(nodes),
+            trigger,
+            restart_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Record that an iteration started `deviation` seconds after (or before, if negative) the
+    /// time it was scheduled for.
+    ///
+    /// Only called by the region thread, so plain loads and stores suffice.
+    pub(crate) fn record_jitter(&self, deviation: f64) {
+        let add = |sum: &AtomicU64, value: f64| {
+            let total = f64::from_bits(sum.load(Ordering::Relaxed)) + value;
+            sum.store(total.to_bits(), Ordering::Relaxed);
+        };
+        add(&self.jitter_sum, deviation);
+        add(&self.jitter_sq_sum, deviation * deviation);
+        self.jitter_samples.fetch_add(1, Ordering::Relaxed);
+        let nanos = (deviation.abs() * 1e9) as u64;
+        self.max_jitter.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Standard deviation of the recorded deviations.
+    fn jitter(&self) -> std::time::Duration {
+        let samples = self.jitter_samples.load(Ordering::Relaxed);
+        if samples == 0 {
+            return std::time::Duration::ZERO;
+        }
+        let n = samples as f64;
+        let mean = f64::from_bits(self.jitter_sum.load(Ordering::Relaxed)) / n;
+        let variance = f64::from_bits(self.jitter_sq_sum.load(Ordering::Relaxed)) / n - mean * mean;
+        std::time::Duration::try_from_secs_f64(variance.max(0.0).sqrt()).unwrap_or_default()
+    }
+
+    pub(crate) fn snapshot(&self) -> RegionMetrics {
+        let nodes = match self.nodes.lock() {
+            Ok(nodes) => nodes.iter().map(|n| n.snapshot()).collect(),
+            Err(poisoned) => poisoned.into_inner().iter().map(|n| n.snapshot()).collect(),
+        };
+        RegionMetrics {
+            name: self.name.clone(),
+            labels: self.labels.clone(),
+            iterations: self.iterations.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            stalls: self.stalls.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            jitter: self.jitter(),
+            max_jitter: std::time::Duration::from_nanos(self.max_jitter.load(Ordering::Relaxed)),
+            nodes,
+        }
+    }
+}
+
+/// Snapshot of the counters of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeMetrics {
+    pub name: String,
+    /// Items received on all `Input` ports of the node since start
+    pub received: u64,
+    /// Calls to `Output::fire` on all output ports of the node since start
+    pub fired: u64,
+    /// Sequence gaps, i.e. lost items, detected on all `Input` ports of the node since start,
+    /// see `ConnectionHandle::gaps`
+    pub gaps: u64,
+    /// Time spent in channel operations sending on all ports of the node since start, see
+    /// `ConnectionHandle::send_time`
+    #[cfg(feature = "contention")]
+    pub send_time: std::time::Duration,
+    /// Time spent in channel operations receiving on all ports of the node since start
+    #[cfg(feature = "contention")]
+    pub recv_time: std::time::Duration,
+}
+
+/// Snapshot of the counters of a region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMetrics {
+    pub name: String,
+    /// User-defined labels of the region, see `RegionBuilder::with_labels`
+    pub labels: BTreeMap<String, String>,
+    /// Finished iterations of the region loop since start
+    pub iterations: u64,
+    /// Iterations that took longer than the tick since start
+    pub overruns: u64,
+    /// Node calls that exceeded the node watchdog threshold since start,
+    /// see `RegionBuilder::with_node_watchdog`
+    pub stalls: u64,
+    /// Restarts after panics since start, see `RegionBuilder::with_restart_policy`
+    pub restarts: u64,
+    /// Standard deviation of the actual loop period from the scheduled one since start
+    ///
+    /// The scheduled period is the tick, stretched by idle backoff and by overruns, so this
+    /// measures how precisely iterations start on time, e.g. to validate the timing of a
+    /// control loop. Includes delays by other regions of a shared executor.
+    pub jitter: std::time::Duration,
+    /// Largest deviation of the actual loop period from the scheduled one since start
+    pub max_jitter: std::time::Duration,
+    /// Metrics of the nodes in the region, in execution order
+    pub nodes: Vec<NodeMetrics>,
+}
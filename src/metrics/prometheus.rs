@@ -0,0 +1,133 @@
+//! Prometheus text exposition format for `RegionMetrics`.
+use std::fmt::Write;
+
+use super::RegionMetrics;
+
+/// Format `metrics` in the Prometheus text exposition format.
+///
+/// All counters are labelled with the `region` and, for node counters, the `node` name, followed
+/// by the labels of the region, see `RegionBuilder::with_labels`.
+/// Only what `RegionMetrics` contains is exported, queue depths and latencies are not collected.
+pub fn format(metrics: &[RegionMetrics]) -> String {
+    let mut out = String::new();
+    let regions = || metrics.iter().map(|r| (format!("region=\"{}\"{}", escape(&r.name), user_labels(r)), r));
+    let nodes = || {
+        metrics.iter().flat_map(|r| {
+            r.nodes.iter().map(move |n| {
+                let labels = format!("region=\"{}\",node=\"{}\"{}", escape(&r.name), escape(&n.name), user_labels(r));
+                (labels, n)
+            })
+        })
+    };
+    counter(
+        &mut out,
+        "flexcore_region_iterations_total",
+        "Finished iterations of the region loop.",
+        regions().map(|(l, r)| (l, r.iterations)),
+    );
+    counter(
+        &mut out,
+        "flexcore_region_overruns_total",
+        "Iterations that took longer than the tick.",
+        regions().map(|(l, r)| (l, r.overruns)),
+    );
+    counter(
+        &mut out,
+        "flexcore_region_stalls_total",
+        "Node calls that exceeded the node watchdog threshold.",
+        regions().map(|(l, r)| (l, r.stalls)),
+    );
+    counter(
+        &mut out,
+        "flexcore_region_restarts_total",
+        "Restarts of the region after panics.",
+        regions().map(|(l, r)| (l, r.restarts)),
+    );
+    gauge(
+        &mut out,
+        "flexcore_region_jitter_seconds",
+        "Standard deviation of the loop period from the scheduled one.",
+        regions().map(|(l, r)| (l, r.jitter.as_secs_f64())),
+    );
+    gauge(
+        &mut out,
+        "flexcore_region_max_jitter_seconds",
+        "Largest deviation of the loop period from the scheduled one.",
+        regions().map(|(l, r)| (l, r.max_jitter.as_secs_f64())),
+    );
+    counter(
+        &mut out,
+        "flexcore_node_received_total",
+        "Items received on all input ports of the node.",
+        nodes().map(|(l, n)| (l, n.received)),
+    );
+    counter(
+        &mut out,
+        "flexcore_node_fired_total",
+        "Items sent on all output ports of the node.",
+        nodes().map(|(l, n)| (l, n.fired)),
+    );
+    counter(
+        &mut out,
+        "flexcore_node_gaps_total",
+        "Gaps in the sequence numbers received on all input ports of the node, each losing one or more items.",
+        nodes().map(|(l, n)| (l, n.gaps)),
+    );
+    #[cfg(feature = "contention")]
+    {
+        counter(
+            &mut out,
+            "flexcore_node_send_seconds_total",
+            "Time spent in channel operations sending on all output ports of the node.",
+            nodes().map(|(l, n)| (l, n.send_time.as_secs_f64())),
+        );
+        counter(
+            &mut out,
+            "flexcore_node_recv_seconds_total",
+            "Time spent in channel operations receiving on all input ports of the node.",
+            nodes().map(|(l, n)| (l, n.recv_time.as_secs_f64())),
+        );
+    }
+    out
+}
+
+/// Write a counter family, `samples` yields the labels and value of each sample.
+fn counter<V: std::fmt::Display>(out: &mut String, name: &str, help: &str, samples: impl Iterator<Item = (String, V)>) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{name}{{{labels}}} {value}");
+    }
+}
+
+/// Write a gauge family, see `counter`.
+fn gauge<V: std::fmt::Display>(out: &mut String, name: &str, help: &str, samples: impl Iterator<Item = (String, V)>) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{name}{{{labels}}} {value}");
+    }
+}
+
+/// Format the estimated bytes buffered in the connections of an infrastructure, see
+/// `Infrastructure::buffered_bytes`.
+pub fn format_buffered_bytes(bytes: u64) -> String {
+    let name = "flexcore_buffered_bytes";
+    format!(
+        "# HELP {name} Estimated bytes buffered in the connections of the infrastructure.\n# TYPE {name} gauge\n{name} {bytes}\n"
+    )
+}
+
+/// Labels of `region` as `,name="value"` pairs.
+fn user_labels(region: &RegionMetrics) -> String {
+    region
+        .labels
+        .iter()
+        .map(|(name, value)| format!(",{name}=\"{}\"", escape(value)))
+        .collect()
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
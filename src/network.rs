@@ -0,0 +1,235 @@
+//! Connections across process boundaries.
+//!
+//! Messages are serialized with a `Codec`, `bincode` per default, and sent over TCP, each one
+//! prefixed by its length as big-endian `u32`. Both ends keep the `fire`/`fetch` semantics of
+//! local connections: the sending side hands its data to a background thread via a regular
+//! channel, the receiving side gets data pushed into a regular channel by background threads.
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{self, Codec};
+use crate::ports::{self, Envelope, Sink, SinkTx, Source, SourceRx};
+use crate::{ConnectionId, Input, Output};
+
+/// Frames larger than this are treated as corrupt stream.
+const MAX_FRAME_LEN: u32 = 64 * 1024 * 1024;
+
+/// Minimum time between two connection attempts of a remote output.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polling interval of a listener checking whether its input port still exists.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl<T: Clone + Serialize + Send + 'static> Output<T> {
+    /// Connect this output to an input port of another process that listens on `addr`.
+    ///
+    /// See `Input::bind` for the other side.
+    ///
+    /// Sending happens in a background thread that lives as long as this port. If the remote
+    /// side is unreachable, the connection is re-established at most once per second. Data fired
+    /// while there is no connection is dropped.
+    ///
+    /// Sequence numbers (see `Input::fetch_sequenced`) are not transferred, the receiving side
+    /// assigns its own.
+    ///
+    /// Data is encoded with `codec::default_format`.
+    pub fn connect_remote(&mut self, addr: impl ToSocketAddrs) -> std::io::Result<ConnectionId> {
+        self.connect_remote_with_codec(addr, codec::default_format())
+    }
+
+    /// Like `Self::connect_remote`, but encode data with `codec`.
+    pub fn connect_remote_with_codec(
+        &mut self,
+        addr: impl ToSocketAddrs,
+        codec: impl Codec,
+    ) -> std::io::Result<ConnectionId> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "address does not resolve to any socket address",
+            ));
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("flexcore-tx".into())
+            .spawn(move || send_loop(&addrs, &rx, &codec))?;
+        let id = ConnectionId::next();
+        self.sinks.push(Sink::new(id, SinkTx::Unbounded(tx), Default::default()));
+        Ok(id)
+    }
+}
+
+impl<T: DeserializeOwned + Send + 'static> Input<T> {
+    /// Receive data on this input port from other processes connecting to `addr`.
+    ///
+    /// See `Output::connect_remote` for the other side.
+    ///
+    /// Returns the address the port is actually bound to, which differs from `addr` if port 0
+    /// was requested. Any number of remote outputs may connect, and reconnect after failures.
+    /// Listening stops once this port is dropped.
+    ///
+    /// Data of all remote outputs is received via a single connection, whose sequence numbers
+    /// (see `Input::fetch_sequenced`) count the messages in order of arrival.
+    ///
+    /// Data is decoded with `codec::default_format`.
+    pub fn bind(&mut self, addr: impl ToSocketAddrs) -> std::io::Result<SocketAddr> {
+        self.bind_with_codec(addr, codec::default_format())
+    }
+
+    /// Like `Self::bind`, but decode data with `codec`.
+    pub fn bind_with_codec(&mut self, addr: impl ToSocketAddrs, codec: impl Codec) -> std::io::Result<SocketAddr> {
+        let codec = Arc::new(codec);
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        let alive = Arc::new(());
+        let weak_alive = Arc::downgrade(&alive);
+        let next_seq = Arc::new(Mutex::new(0));
+        std::thread::Builder::new()
+            .name("flexcore-listen".into())
+            .spawn(move || accept_loop(&listener, &tx, &next_seq, &weak_alive, &codec))?;
+        self.sources.push(Source {
+            id: ConnectionId::next(),
+            rx: SourceRx::Envelopes(rx),
+            stats: Default::default(),
+            label: None,
+            peeked: Default::default(),
+        });
+        self.remote_guards.push(alive);
+        Ok(local_addr)
+    }
+}
+
+fn send_loop<T: Serialize>(addrs: &[SocketAddr], rx: &Receiver<Envelope<T>>, codec: &impl Codec) {
+    let mut stream: Option<BufWriter<TcpStream>> = None;
+    let mut last_attempt: Option<Instant> = None;
+    let mut dropped: usize = 0;
+    // Ends once the output port is dropped.
+    while let Ok(Envelope { data, .. }) = rx.recv() {
+        if stream.is_none() && last_attempt.is_none_or(|t| t.elapsed() >= RECONNECT_INTERVAL) {
+            last_attempt = Some(Instant::now());
+            match TcpStream::connect(addrs) {
+                Ok(s) => {
+                    log::info!("Connected remote output to {}", addrs[0]);
+                    if dropped > 0 {
+                        log::warn!("Dropped {dropped} messages while {} was unreachable", addrs[0]);
+                        dropped = 0;
+                    }
+                    stream = Some(BufWriter::new(s));
+                }
+                Err(e) => log::warn!("Could not connect remote output to {}: {e}", addrs[0]),
+            }
+        }
+        let Some(s) = &mut stream else {
+            dropped += 1;
+            continue;
+        };
+        let payload = match codec.encode(&data) {
+            Ok(p) => p,
+            Err(e) => {
+                log::error!("Could not serialize message: {e}");
+                continue;
+            }
+        };
+        let Ok(len) = u32::try_from(payload.len()) else {
+            log::error!("Message of {} bytes is too large to send", payload.len());
+            continue;
+        };
+        let res = s
+            .write_all(&len.to_be_bytes())
+            .and_then(|_| s.write_all(&payload))
+            .and_then(|_| s.flush());
+        if let Err(e) = res {
+            log::warn!("Lost remote connection to {}: {e}", addrs[0]);
+            stream = None;
+            dropped += 1;
+        }
+    }
+}
+
+fn accept_loop<T: DeserializeOwned + Send + 'static, C: Codec>(
+    listener: &TcpListener,
+    tx: &Sender<Envelope<T>>,
+    next_seq: &Arc<Mutex<u64>>,
+    alive: &Weak<()>,
+    codec: &Arc<C>,
+) {
+    while alive.strong_count() > 0 {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                log::info!("Accepted remote connection from {peer}");
+                let tx = tx.clone();
+                let next_seq = next_seq.clone();
+                let codec = codec.clone();
+                let spawn_res = std::thread::Builder::new()
+                    .name("flexcore-rx".into())
+                    .spawn(move || receive_loop(stream, peer, &tx, &next_seq, codec.as_ref()));
+                if let Err(e) = spawn_res {
+                    log::error!("Could not start receiver thread for {peer}: {e}");
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(e) => log::warn!("Could not accept remote connection: {e}"),
+        }
+    }
+}
+
+fn receive_loop<T: DeserializeOwned>(
+    stream: TcpStream,
+    peer: SocketAddr,
+    tx: &Sender<Envelope<T>>,
+    next_seq: &Mutex<u64>,
+    codec: &impl Codec,
+) {
+    if let Err(e) = stream.set_nonblocking(false) {
+        log::error!("Could not configure connection from {peer}: {e}");
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut len = [0u8; 4];
+        if let Err(e) = reader.read_exact(&mut len) {
+            if e.kind() != std::io::ErrorKind::UnexpectedEof {
+                log::warn!("Lost remote connection from {peer}: {e}");
+            }
+            return;
+        }
+        let len = u32::from_be_bytes(len);
+        if len > MAX_FRAME_LEN {
+            log::error!("Received frame of {len} bytes from {peer}, closing connection");
+            return;
+        }
+        let mut payload = vec![0u8; len as usize];
+        if let Err(e) = reader.read_exact(&mut payload) {
+            log::warn!("Lost remote connection from {peer}: {e}");
+            return;
+        }
+        match codec.decode(&payload) {
+            Ok(data) => {
+                // Locked while sending to keep sequence numbers in order of arrival.
+                let mut seq = next_seq.lock().unwrap_or_else(|e| e.into_inner());
+                let envelope = Envelope {
+                    seq: *seq,
+                    stamp: ports::next_stamp(),
+                    data,
+                };
+                if tx.send(envelope).is_err() {
+                    // Input port has been dropped.
+                    return;
+                }
+                *seq += 1;
+            }
+            Err(e) => log::error!("Could not deserialize message from {peer}: {e}"),
+        }
+    }
+}
@@ -1,7 +1,93 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+use crate::ports::{AnyInput, AnyOutput};
+
+thread_local! {
+    /// End of the time slice of the region iteration that is currently processed.
+    static YIELD_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+
+    /// Timing of the region iteration that is currently processed.
+    static TICK_CONTEXT: Cell<Option<TickContext>> = const { Cell::new(None) };
+
+    /// Name of the region that is currently processed, empty outside of region iterations.
+    static CURRENT_REGION: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Set by the region loop for each iteration, see `current_region`.
+pub(crate) fn set_current_region(region: &str) {
+    CURRENT_REGION.with(|r| {
+        let mut r = r.borrow_mut();
+        r.clear();
+        r.push_str(region);
+    });
+}
+
+/// Name of the region the calling node is executed in, `None` outside of node calls.
+pub(crate) fn current_region() -> Option<String> {
+    CURRENT_REGION.with(|r| {
+        let r = r.borrow();
+        (!r.is_empty()).then(|| r.clone())
+    })
+}
+
+/// Timing of the current iteration of a region loop, see `tick_context`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TickContext {
+    /// Number of the iteration, starting at 0 for the first one of the region
+    pub tick_index: u64,
+    /// Time since the first iteration of the region started
+    pub uptime: Duration,
+    /// Actual time since the previous iteration started, the configured tick for the first one
+    ///
+    /// Use it for time-correct integration instead of assuming the configured tick, which is
+    /// exceeded on overruns and stretched by idle backoff.
+    pub dt: Duration,
+}
+
+/// Set by the region loop for each iteration, see `tick_context`.
+pub(crate) fn set_tick_context(context: Option<TickContext>) {
+    TICK_CONTEXT.with(|c| c.set(context));
+}
+
+/// Timing of the region iteration the calling node is executed in.
+///
+/// Available in `Node::tick` and `Node::process_input`, `None` outside of them.
+pub fn tick_context() -> Option<TickContext> {
+    TICK_CONTEXT.with(Cell::get)
+}
+
+/// Set by the region loop for each iteration, see `should_yield`.
+pub(crate) fn set_yield_deadline(deadline: Option<Instant>) {
+    YIELD_DEADLINE.with(|d| d.set(deadline));
+}
+
+/// Whether the current region iteration has used up its time slice.
+///
+/// The time slice is the tick of the region or, if shorter, its budget (see
+/// `RegionBuilder::with_budget`). Nodes doing heavy work can check this hint and continue in
+/// their next call, which keeps other regions on a shared executor (see
+/// `RegionBuilder::with_executor`) and the following nodes responsive.
+///
+/// Always false outside of node calls.
+pub fn should_yield() -> bool {
+    YIELD_DEADLINE.with(Cell::get).is_some_and(|deadline| Instant::now() >= deadline)
+}
+
 /// Trait that describes a Node used in a Region.
-pub trait Node: Send {
+///
+/// Nodes must be `'static`, see `AsAny`.
+pub trait Node: Send + AsAny {
     fn name(&self) -> &String;
 
+    /// Called once by the region thread before the first tick, e.g. to open a device.
+    ///
+    /// See `InfrastructureBuilder::with_startup_phase` to order the start of regions.
+    ///
+    /// Per default, this is noop.
+    fn on_start(&mut self) {}
+
     /// Specify special behavior that should happen on each tick, e.g. reading a device
     /// or firing on output nodes.
     ///
@@ -11,9 +97,133 @@ pub trait Node: Send {
     /// Here the use shall read all `Input` ports, process the data accordingly,
     /// and fire outputs that are related to it.
     ///
-    /// # TODO
+    /// Per default, this is noop, e.g. for source nodes without inputs.
+    fn process_input(&mut self) {}
+
+    /// Write data buffered during `tick` and `process_input` to the outputs, e.g. to emit a
+    /// batch once per iteration.
+    ///
+    /// Called in every iteration of the region loop right after `process_input` of this node,
+    /// or for a `NodeGroup`, after `process_input` of all nodes of the group, in the same order.
+    /// Then the next node or group of the region is ticked, so data flushed here reaches nodes
+    /// later in the region within the same iteration. Unlike `tick`, it is not skipped when the
+    /// region budget is exhausted (see `RegionBuilder::with_budget`).
+    ///
+    /// Per default, this is noop.
+    fn flush(&mut self) {}
+
+    /// Maximum number of items this node receives from its `Input` ports per tick.
+    ///
+    /// The limit is shared by all inputs of the node and applies to `Input::fetch` calls in both
+    /// `tick` and `process_input`. Items exceeding the limit stay buffered in their connections
+    /// and are handed out in the following ticks, so a burst is smoothed out over several ticks
+    /// instead of blowing the timing of a single one. If the input rate stays above the limit,
+    /// the buffers grow without bound.
+    ///
+    /// Per default, this is unlimited.
+    fn max_items_per_tick(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether this node is ready to `tick`, e.g. once it received a calibration message.
+    ///
+    /// Checked before each `tick`. While the node is not ready, its `tick` is skipped, but
+    /// `process_input` and `flush` are still called, so the node can receive what it waits for.
+    /// The transition to ready is logged.
+    ///
+    /// Per default, the node is always ready.
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Whether this node has completed its work, e.g. a source that has read its whole file.
+    ///
+    /// Allows finite pipelines: a region stops once all of its nodes are finished, see
+    /// `Region` for the exact condition. `Input::is_exhausted` tells whether all upstream nodes
+    /// are gone and all their data has been read.
+    ///
+    /// Per default, a node is never finished.
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// The input port called `name`.
+    ///
+    /// Makes the port available for connecting by name, e.g. with
+    /// `InfrastructureBuilder::connect`. The `ports!` macro generates a matching lookup.
+    ///
+    /// Per default, the node has no named input ports.
+    fn input_port(&mut self, _name: &str) -> Option<&mut dyn AnyInput> {
+        None
+    }
+
+    /// The output port called `name`.
+    ///
+    /// Makes the port available for connecting by name, e.g. with
+    /// `InfrastructureBuilder::connect`. The `ports!` macro generates a matching lookup.
+    ///
+    /// Per default, the node has no named output ports.
+    fn output_port(&mut self, _name: &str) -> Option<&mut dyn AnyOutput> {
+        None
+    }
+
+    /// Names of the ports available via `input_port`, e.g. to check them with
+    /// `InfrastructureBuilder::dry_run`. The `ports!` macro generates a matching list.
+    ///
+    /// Per default, the node has no named input ports.
+    fn input_port_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Names of the ports available via `output_port`, see `Self::input_port_names`.
+    ///
+    /// Per default, the node has no named output ports.
+    fn output_port_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Serialized state to carry over into a new instance of this node, see
+    /// `Infrastructure::reload`. The format is up to the node.
+    ///
+    /// Per default, the node has no state worth keeping.
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Continue from `state` returned by `Self::snapshot` of the previous instance of this node.
     ///
-    /// Input reading should happen automatically at each tick.
-    /// The user should specify what to do with the received data.
-    fn process_input(&mut self);
+    /// Called before `Self::on_start`. Nodes whose format changed in between should log and
+    /// ignore the state.
+    fn restore(&mut self, _state: &[u8]) {}
+}
+
+/// Access to the concrete type of a node, e.g. to downcast it, see `Infrastructure::with_node`.
+///
+/// Implemented for all `'static` nodes, so also boxed nodes, e.g. of
+/// `RegionBuilder::with_boxed_node`, can be downcast to their type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Node + 'static> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Node with parameters that can be changed while running, see `Infrastructure::reconfigure`.
+///
+/// Kept apart from `Node`, whose methods must not depend on the concrete node type.
+pub trait Reconfigurable: Node {
+    /// Parameters of the node, e.g. gains or thresholds.
+    type Config: Send + 'static;
+
+    /// Apply `config`, called on the region thread between two loop iterations.
+    fn reconfigure(&mut self, config: Self::Config);
 }
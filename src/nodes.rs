@@ -0,0 +1,9 @@
+//! Generic helper nodes that are useful in many pipelines.
+mod delay_line;
+pub use delay_line::DelayLine;
+
+mod tee;
+pub use tee::Tee;
+
+mod window;
+pub use window::{Window, WindowSize};
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::{Input, Node, Output};
+
+/// Node that fires every item received on its input after a fixed delay, e.g. for timeouts.
+///
+/// Items are checked in each iteration of the region loop, so they are fired up to one tick
+/// late, and up to 10 ms later while the region backs off automatically (see
+/// `RegionBuilder::with_strict_periodicity`) or more with `RegionBuilder::with_idle_backoff`.
+/// Any number of items can be pending, each with its own deadline. Items are fired in the order
+/// they were received.
+///
+/// Pending items are cancelled by sending their key to the cancel input (see
+/// `Self::cancel_mut`), e.g. an acknowledgement. The key of an item is computed by the function
+/// given to `Self::with_key`. A `DelayLine` created with `Self::new` has `()` as key for all
+/// items, so any message on the cancel input cancels all pending items. Items and cancellations
+/// received in the same iteration are applied in this order.
+pub struct DelayLine<T: Clone, K = ()> {
+    name: String,
+    delay: Duration,
+    key: Box<dyn Fn(&T) -> K + Send>,
+    input: Input<T>,
+    cancel: Input<K>,
+    output: Output<T>,
+    /// Items with their deadline, ordered by deadline
+    pending: VecDeque<(Instant, T)>,
+}
+
+impl<T: Clone> DelayLine<T> {
+    /// Create a delay line firing items `delay` after their reception.
+    pub fn new(name: impl Into<String>, delay: Duration) -> Self {
+        Self::with_key(name, delay, |_| ())
+    }
+}
+
+impl<T: Clone, K> DelayLine<T, K> {
+    /// Create a delay line whose pending items are cancelled by their key, computed by `key`.
+    pub fn with_key(name: impl Into<String>, delay: Duration, key: impl Fn(&T) -> K + Send + 'static) -> Self {
+        Self {
+            name: name.into(),
+            delay,
+            key: Box::new(key),
+            input: Input::default(),
+            cancel: Input::default(),
+            output: Output::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The input port of the items to delay.
+    pub fn input_mut(&mut self) -> &mut Input<T> {
+        &mut self.input
+    }
+
+    /// The input port receiving keys of pending items to cancel.
+    pub fn cancel_mut(&mut self) -> &mut Input<K> {
+        &mut self.cancel
+    }
+
+    /// The output port of the delayed items.
+    pub fn output_mut(&mut self) -> &mut Output<T> {
+        &mut self.output
+    }
+
+    /// Number of items waiting for their deadline.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<T: Clone + Send + 'static, K: PartialEq + Send + 'static> Node for DelayLine<T, K> {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        let now = Instant::now();
+        let deadline = now + self.delay;
+        self.pending.extend(self.input.fetch().into_iter().map(|data| (deadline, data)));
+        let cancelled = self.cancel.fetch();
+        if !cancelled.is_empty() {
+            let key = &self.key;
+            self.pending.retain(|(_, data)| !cancelled.contains(&key(data)));
+        }
+        while self.pending.front().is_some_and(|(deadline, _)| *deadline <= now) {
+            if let Some((_, data)) = self.pending.pop_front() {
+                self.output.fire(data);
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{Input, Node, Output};
+
+/// Node that broadcasts every item received on its input to all of its outputs.
+///
+/// Useful to tap into the middle of a pipeline, e.g. for logging.
+pub struct Tee<T: Clone> {
+    name: String,
+    input: Input<T>,
+    outputs: Vec<Output<T>>,
+}
+
+impl<T: Clone> Tee<T> {
+    /// Create a tee with `n` outputs.
+    pub fn new(name: impl Into<String>, n: usize) -> Self {
+        Self {
+            name: name.into(),
+            input: Input::default(),
+            outputs: (0..n).map(|_| Output::default()).collect(),
+        }
+    }
+
+    /// The input port to connect the stream to split to.
+    pub fn input_mut(&mut self) -> &mut Input<T> {
+        &mut self.input
+    }
+
+    /// The `i`-th output port, `None` if `i` is out of range.
+    pub fn output_mut(&mut self, i: usize) -> Option<&mut Output<T>> {
+        self.outputs.get_mut(i)
+    }
+
+    /// All output ports.
+    pub fn outputs_mut(&mut self) -> &mut [Output<T>] {
+        &mut self.outputs
+    }
+}
+
+impl<T: Clone + Send + 'static> Node for Tee<T> {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        for data in self.input.fetch() {
+            if let Some((last, others)) = self.outputs.split_last_mut() {
+                for out in others {
+                    out.fire(data.clone());
+                }
+                last.fire(data);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::{Input, Node, Output};
+
+/// Extent of the window of a `Window` node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowSize {
+    /// The last items, at least 1
+    Count(usize),
+    /// The items received within this duration
+    Time(Duration),
+}
+
+/// Computes the summary of the items of a window.
+type Summarize<T, S> = Box<dyn FnMut(&[T]) -> S + Send>;
+
+/// Node that emits summaries of a window of the items received on its input, e.g. rolling
+/// statistics for telemetry.
+///
+/// The summary is computed by a closure from the items of the window in the order they were
+/// received. A sliding window (see `Self::sliding`) moves with every item and emits a summary
+/// for each one. A tumbling window (see `Self::tumbling`) emits a summary once it is complete
+/// and then starts over empty, so every item is summarized once.
+///
+/// Items are timestamped when they are fetched, so time windows have the resolution of the
+/// region tick. Tumbling time windows follow each other from the first iteration of the node
+/// and are closed in the first iteration after their end, windows without items emit nothing.
+pub struct Window<T, S: Clone> {
+    name: String,
+    size: WindowSize,
+    tumbling: bool,
+    summarize: Summarize<T, S>,
+    input: Input<T>,
+    output: Output<S>,
+    items: VecDeque<T>,
+    /// Reception time of each item of `items`, only kept for time windows
+    received: VecDeque<Instant>,
+    /// Start of the current tumbling time window
+    window_start: Option<Instant>,
+}
+
+impl<T, S: Clone> Window<T, S> {
+    /// Create a node emitting a summary of the window ending with each received item.
+    pub fn sliding(name: impl Into<String>, size: WindowSize, summarize: impl FnMut(&[T]) -> S + Send + 'static) -> Self {
+        Self::new(name.into(), size, false, Box::new(summarize))
+    }
+
+    /// Create a node emitting a summary of each complete window, windows do not overlap.
+    pub fn tumbling(name: impl Into<String>, size: WindowSize, summarize: impl FnMut(&[T]) -> S + Send + 'static) -> Self {
+        Self::new(name.into(), size, true, Box::new(summarize))
+    }
+
+    fn new(name: String, size: WindowSize, tumbling: bool, summarize: Summarize<T, S>) -> Self {
+        Self {
+            name,
+            size: match size {
+                WindowSize::Count(n) => WindowSize::Count(n.max(1)),
+                time => time,
+            },
+            tumbling,
+            summarize,
+            input: Input::default(),
+            output: Output::default(),
+            items: VecDeque::new(),
+            received: VecDeque::new(),
+            window_start: None,
+        }
+    }
+
+    /// The input port of the items to summarize.
+    pub fn input_mut(&mut self) -> &mut Input<T> {
+        &mut self.input
+    }
+
+    /// The output port of the summaries.
+    pub fn output_mut(&mut self) -> &mut Output<S> {
+        &mut self.output
+    }
+
+    /// Number of items in the current window.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether the current window has no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn emit(&mut self) {
+        let summary = (self.summarize)(self.items.make_contiguous());
+        self.output.fire(summary);
+    }
+
+    /// Drop items that have left a sliding window at `now`.
+    fn evict(&mut self, now: Instant) {
+        match self.size {
+            WindowSize::Count(n) => {
+                while self.items.len() > n {
+                    self.items.pop_front();
+                }
+            }
+            WindowSize::Time(duration) => {
+                while self.received.front().is_some_and(|t| now - *t > duration) {
+                    self.received.pop_front();
+                    self.items.pop_front();
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+        self.received.clear();
+    }
+}
+
+impl<T: Send + 'static, S: Clone + Send + 'static> Node for Window<T, S> {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        let now = Instant::now();
+        let window_start = *self.window_start.get_or_insert(now);
+        if let (true, WindowSize::Time(duration)) = (self.tumbling, self.size) {
+            if now - window_start >= duration {
+                if !self.items.is_empty() {
+                    self.emit();
+                    self.clear();
+                }
+                // Windows keep their length, also if the node fell behind.
+                let elapsed = (now - window_start).as_nanos() / duration.as_nanos().max(1);
+                let skipped = duration.saturating_mul(u32::try_from(elapsed).unwrap_or(u32::MAX));
+                self.window_start = Some(window_start + skipped);
+            }
+        }
+        for item in self.input.fetch() {
+            self.items.push_back(item);
+            if let WindowSize::Time(_) = self.size {
+                self.received.push_back(now);
+            }
+            match (self.tumbling, self.size) {
+                (false, _) => {
+                    self.evict(now);
+                    self.emit();
+                }
+                (true, WindowSize::Count(n)) if self.items.len() >= n => {
+                    self.emit();
+                    self.clear();
+                }
+                (true, _) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Reporting of panics in region threads.
+//!
+//! Panic hooks are global in Rust, so flexcore installs a single hook the first time an
+//! infrastructure is built. It wraps the hook that was set before: panics of region threads
+//! are passed to the hook of their infrastructure (see `InfrastructureBuilder::with_panic_hook`)
+//! and then, if enabled, to the previous hook (see `InfrastructureBuilder::with_previous_panic_hook`),
+//! all others only to the previous hook.
+use std::cell::RefCell;
+use std::sync::{Arc, Once};
+
+use crate::metrics::NodeCounters;
+
+/// Called with the details of a panic in a region thread, see
+/// `InfrastructureBuilder::with_panic_hook`.
+pub type PanicHook = Arc<dyn Fn(&PanicReport) + Send + Sync>;
+
+thread_local! {
+    /// Hook of the infrastructure running on this thread and whether the previous hook is called
+    /// after it, `None` outside of region threads.
+    static HOOK: RefCell<Option<(PanicHook, bool)>> = const { RefCell::new(None) };
+
+    /// Node whose method is currently executed on this thread.
+    static CURRENT_NODE: RefCell<Option<Arc<NodeCounters>>> = const { RefCell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// A panic in a region thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    /// Name of the thread, see `InfrastructureBuilder::with_thread_name_prefix`
+    pub thread: String,
+    /// Region being processed, `None` while starting or between iterations
+    pub region: Option<String>,
+    /// Node being called, `None` if the panic happened outside of node methods
+    pub node: Option<String>,
+    /// Panic message
+    pub message: String,
+    /// Source location of the panic
+    pub location: Option<String>,
+}
+
+impl std::fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Thread {}", self.thread)?;
+        if let Some(region) = &self.region {
+            write!(f, ", region {region}")?;
+        }
+        if let Some(node) = &self.node {
+            write!(f, ", node {node}")?;
+        }
+        write!(f, " panicked")?;
+        if let Some(location) = &self.location {
+            write!(f, " at {location}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Hook used unless `InfrastructureBuilder::with_panic_hook` is given, logs the panic as error.
+pub(crate) fn log_panic(report: &PanicReport) {
+    log::error!("{report}");
+}
+
+/// Wrap the current global panic hook, once per process.
+pub(crate) fn install() {
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Cloned, so a panicking hook cannot run into a borrowed `HOOK`.
+            let hook = HOOK.try_with(|h| h.try_borrow().ok().and_then(|h| h.clone())).ok().flatten();
+            let Some((hook, chain)) = hook else {
+                previous(info);
+                return;
+            };
+            let report = PanicReport {
+                thread: std::thread::current().name().unwrap_or("<unnamed>").into(),
+                region: crate::node::current_region(),
+                node: CURRENT_NODE
+                    .try_with(|n| n.try_borrow().ok().and_then(|n| n.as_ref().map(|n| n.name.clone())))
+                    .ok()
+                    .flatten(),
+                message: crate::shutdown::panic_message(info.payload()),
+                location: info.location().map(|l| l.to_string()),
+            };
+            hook(&report);
+            if chain {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Passes panics of the current thread to a hook while alive.
+pub(crate) struct HookGuard {
+    previous: Option<(PanicHook, bool)>,
+}
+
+impl HookGuard {
+    /// Pass panics to `hook`, and then to the previous global hook if `chain` is set.
+    pub(crate) fn set(hook: PanicHook, chain: bool) -> Self {
+        install();
+        Self {
+            previous: HOOK.with(|h| h.borrow_mut().replace((hook, chain))),
+        }
+    }
+}
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = HOOK.try_with(|h| *h.borrow_mut() = previous);
+        // Left behind by a node that panicked
+        let _ = CURRENT_NODE.try_with(|n| n.borrow_mut().take());
+    }
+}
+
+/// Record the node whose method is called on this thread, `None` once the call returned.
+pub(crate) fn set_current_node(node: Option<Arc<NodeCounters>>) {
+    CURRENT_NODE.with(|n| *n.borrow_mut() = node);
+}
@@ -1,50 +1,2104 @@
-#[derive(Default)]
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, OnceLock};
+
+use crate::bus::{self, Subscribers};
+use crate::{FlexcoreError, ShutdownToken};
+
+thread_local! {
+    /// Number of items the node that is currently processed may still fetch in this tick.
+    ///
+    /// `None` means unlimited. Set by the region loop, see `Node::max_items_per_tick`.
+    static FETCH_BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+
+    /// Number of items received by all `Input` ports on this thread.
+    static RECEIVED: Cell<u64> = const { Cell::new(0) };
+
+    /// Number of `Output::fire` calls on this thread.
+    static FIRED: Cell<u64> = const { Cell::new(0) };
+
+    /// Number of sequence gaps detected by all `Input` ports on this thread.
+    static GAPS: Cell<u64> = const { Cell::new(0) };
+
+    /// Nanoseconds spent in channel operations on this thread, sending and receiving.
+    #[cfg(feature = "contention")]
+    static CHANNEL_NANOS: Cell<[u64; 2]> = const { Cell::new([0; 2]) };
+
+    /// Filter of the region that is currently processed, see `RegionBuilder::with_inbound_filter`.
+    static INBOUND_FILTER: RefCell<Option<InboundFilter>> = const { RefCell::new(None) };
+
+    /// Budget of the infrastructure running on this thread, see `set_thread_memory_budget`.
+    static MEMORY_BUDGET: RefCell<Option<Arc<MemoryBudget>>> = const { RefCell::new(None) };
+}
+
+/// Filter applied to all data fetched by the nodes of a region, see
+/// `RegionBuilder::with_inbound_filter`.
+pub type InboundFilter = Box<dyn FnMut(&dyn Any) -> bool + Send>;
+
+/// Filter applied to the data fetched by a single input port, see `Input::with_filter`.
+type PortFilter<T> = Box<dyn FnMut(&T) -> bool + Send>;
+
+/// Apply `filter` to all data fetched on this thread until it is taken back with
+/// `take_inbound_filter`.
+pub(crate) fn set_inbound_filter(filter: Option<InboundFilter>) {
+    INBOUND_FILTER.with(|f| *f.borrow_mut() = filter);
+}
+
+/// Remove the filter set by `set_inbound_filter`.
+pub(crate) fn take_inbound_filter() -> Option<InboundFilter> {
+    INBOUND_FILTER.with(|f| f.borrow_mut().take())
+}
+
+/// Whether `data` passes the filter of the port and the filter of the current region.
+fn accepts<T: 'static>(data: &T, filter: &mut Option<PortFilter<T>>) -> bool {
+    if !filter.as_mut().is_none_or(|f| f(data)) {
+        return false;
+    }
+    INBOUND_FILTER.with(|f| match f.try_borrow_mut() {
+        Ok(mut f) => f.as_mut().is_none_or(|f| f(data)),
+        // Fetched from within the region filter itself
+        Err(_) => true,
+    })
+}
+
+/// Number of items received by all `Input` ports on this thread so far.
+///
+/// Used by the region loop to detect whether an iteration had any work and to attribute
+/// received items to nodes.
+pub(crate) fn thread_received_count() -> u64 {
+    RECEIVED.with(Cell::get)
+}
+
+/// Number of `Output::fire` calls on this thread so far.
+pub(crate) fn thread_fired_count() -> u64 {
+    FIRED.with(Cell::get)
+}
+
+/// Number of sequence gaps detected by all `Input` ports on this thread so far, see
+/// `ConnectionHandle::gaps`.
+pub(crate) fn thread_gap_count() -> u64 {
+    GAPS.with(Cell::get)
+}
+
+/// Count a `fire` call of an output port.
+pub(crate) fn count_fire() {
+    FIRED.with(|f| f.set(f.get() + 1));
+}
+
+/// Nanoseconds spent sending and receiving in channel operations on this thread so far.
+#[cfg(feature = "contention")]
+pub(crate) fn thread_channel_nanos() -> [u64; 2] {
+    CHANNEL_NANOS.with(Cell::get)
+}
+
+/// Channel operation measured by `timed`.
+#[derive(Copy, Clone)]
+enum ChannelOp {
+    Send = 0,
+    Recv = 1,
+}
+
+/// Run the channel operation `f` of a connection, measuring its duration if the `contention`
+/// feature is enabled.
+#[cfg(feature = "contention")]
+fn timed<R>(op: ChannelOp, stats: &ConnectionStats, f: impl FnOnce() -> R) -> R {
+    let start = std::time::Instant::now();
+    let ret = f();
+    let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    stats.channel_nanos[op as usize].fetch_add(nanos, Ordering::Relaxed);
+    CHANNEL_NANOS.with(|c| {
+        let mut total = c.get();
+        total[op as usize] += nanos;
+        c.set(total);
+    });
+    ret
+}
+
+#[cfg(not(feature = "contention"))]
+#[inline(always)]
+fn timed<R>(_op: ChannelOp, _stats: &ConnectionStats, f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Number of items `Input::fetch` calls on this thread may still return.
+pub(crate) fn fetch_budget() -> Option<usize> {
+    FETCH_BUDGET.with(Cell::get)
+}
+
+/// Limit the number of items all `Input::fetch` calls on this thread may return until reset.
+pub(crate) fn set_fetch_budget(budget: Option<usize>) {
+    FETCH_BUDGET.with(|b| b.set(budget));
+}
+
+/// Number of connections whose sending end is alive, in the whole process.
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of connections whose output side has not been dropped yet, in the whole process.
+pub(crate) fn open_connection_count() -> usize {
+    OPEN_CONNECTIONS.load(Ordering::Relaxed)
+}
+
+/// Limit of the bytes buffered in the connections of an infrastructure, see
+/// `InfrastructureBuilder::with_memory_budget`.
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    limit: u64,
+    /// Estimated bytes buffered in all connections of the infrastructure
+    buffered: AtomicU64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            buffered: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn buffered_bytes(&self) -> u64 {
+        self.buffered.load(Ordering::Relaxed)
+    }
+}
+
+/// Count data sent from this thread on connections without a budget yet towards `budget`.
+///
+/// Set by region threads, so connections belong to the infrastructure of the node that sends
+/// on them first.
+pub(crate) fn set_thread_memory_budget(budget: Option<Arc<MemoryBudget>>) {
+    MEMORY_BUDGET.with(|b| *b.borrow_mut() = budget);
+}
+
+/// Whether every message is logged, see `InfrastructureBuilder::with_message_tracing`.
+static MESSAGE_TRACING: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn enable_message_tracing() {
+    MESSAGE_TRACING.store(true, Ordering::Relaxed);
+}
+
+/// Renders data for message tracing, see `Input::trace_with_debug`.
+type TraceFormatter<T> = Option<fn(&T) -> String>;
+
+/// Log a message crossing connection `id` if message tracing is enabled.
+#[inline]
+fn trace<T>(action: &str, id: ConnectionId, data: &T, debug: TraceFormatter<T>) {
+    if !MESSAGE_TRACING.load(Ordering::Relaxed) || !log::log_enabled!(log::Level::Trace) {
+        return;
+    }
+    match debug {
+        Some(debug) => log::trace!("{action} {id} {}: {}", std::any::type_name::<T>(), debug(data)),
+        None => log::trace!(
+            "{action} {id} {} ({} bytes)",
+            std::any::type_name::<T>(),
+            std::mem::size_of::<T>()
+        ),
+    }
+}
+
+fn format_debug<T: std::fmt::Debug>(data: &T) -> String {
+    format!("{data:?}")
+}
+
+/// Identifies a connection between an output and an input port.
+///
+/// Unique within the process.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionId(pub(crate) u64);
+
+impl ConnectionId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl std::fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Data as transferred over a connection.
+pub(crate) struct Envelope<T> {
+    /// Position in the stream of the connection
+    pub(crate) seq: u64,
+    /// Logical send time, see `next_stamp`
+    pub(crate) stamp: u64,
+    pub(crate) data: T,
+}
+
+/// Whether items are stamped, see `Input::with_time_order`.
+static TIME_ORDER: AtomicBool = AtomicBool::new(false);
+
+/// Stamp items sent from now on, once per process.
+fn enable_time_order() {
+    if !TIME_ORDER.swap(true, Ordering::Relaxed) {
+        log::debug!("Stamping all items for time-ordered input ports");
+    }
+}
+
+/// Position of the next item sent in the order of all items sent in the process, 0 unless
+/// time order is enabled.
+///
+/// Used as a logical timestamp by `Input::fetch_time_ordered`: unlike wall-clock time, it
+/// orders items sent at the same instant as well.
+pub(crate) fn next_stamp() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    if !TIME_ORDER.load(Ordering::Relaxed) {
+        return 0;
+    }
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Received data together with its position in the stream of its connection.
+///
+/// Sequence numbers of a connection start at 0 and increase by one with each message sent.
+/// A gap between two consecutive items of the same connection means that messages have been lost.
+/// Input ports count such gaps on their own, see `ConnectionHandle::gaps` and
+/// `metrics::NodeMetrics::gaps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequenced<T> {
+    pub connection: ConnectionId,
+    pub seq: u64,
+    pub data: T,
+}
+
+/// Counters of a connection, shared by both of its ends.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionStats {
+    /// Items written to the connection
+    sent: AtomicU64,
+    /// Items read from the connection
+    received: AtomicU64,
+    /// Items lost, e.g. because the input port has been dropped
+    dropped: AtomicU64,
+    /// Whether the sending end has been dropped
+    closed: AtomicBool,
+    /// Whether the receiving end has been dropped
+    input_closed: AtomicBool,
+    /// Nanoseconds spent sending and receiving, see `ConnectionHandle::send_time`
+    #[cfg(feature = "contention")]
+    channel_nanos: [AtomicU64; 2],
+    /// Attempts of `Output::fire_blocking` that found the connection full
+    #[cfg(feature = "contention")]
+    send_retries: AtomicU64,
+    /// Estimated size of a buffered item, 0 for connections whose buffer flexcore does not
+    /// read itself, e.g. remote ones
+    item_size: u64,
+    /// Budget of the infrastructure that sent on this connection first, if it has one
+    budget: OnceLock<Arc<MemoryBudget>>,
+    /// Part of the budget held by this connection
+    buffered_bytes: AtomicU64,
+    /// Sequence number of the next item expected by the input port
+    next_seq: AtomicU64,
+    /// Times the sequence numbers read skipped ahead, see `ConnectionHandle::gaps`
+    gaps: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Reserve room for an item to send in the memory budget, `false` if it is exhausted.
+    ///
+    /// Free of shared writes without a budget.
+    fn reserve(&self) -> bool {
+        if self.item_size == 0 {
+            return true;
+        }
+        let budget = match self.budget.get() {
+            Some(budget) => budget,
+            None => {
+                let budget = MEMORY_BUDGET.with(|b| b.borrow().clone());
+                let Some(budget) = budget else {
+                    return true;
+                };
+                self.budget.get_or_init(|| budget)
+            }
+        };
+        let reserved = budget.buffered.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bytes| {
+            bytes.checked_add(self.item_size).filter(|bytes| *bytes <= budget.limit)
+        });
+        if reserved.is_err() {
+            return false;
+        }
+        self.buffered_bytes.fetch_add(self.item_size, Ordering::SeqCst);
+        true
+    }
+
+    /// Free the room of an item that has been read or could not be sent.
+    fn release(&self) {
+        let Some(budget) = self.budget.get() else {
+            return;
+        };
+        let released = self
+            .buffered_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bytes| bytes.checked_sub(self.item_size));
+        // Nothing left if the input port has been dropped meanwhile, see `Self::release_all`.
+        if released.is_ok() {
+            budget.buffered.fetch_sub(self.item_size, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a gap if `seq`, read by the input port, skips ahead of the expected number.
+    fn check_seq(&self, id: ConnectionId, seq: u64) {
+        let expected = self.next_seq.swap(seq + 1, Ordering::Relaxed);
+        if seq > expected {
+            log::debug!("Connection {id} lost {} items before item {seq}", seq - expected);
+            self.gaps.fetch_add(1, Ordering::Relaxed);
+            GAPS.with(|g| g.set(g.get() + 1));
+        }
+    }
+
+    /// Number of items written to the connection that have been neither read nor dropped, 0
+    /// once the input port has been dropped.
+    fn depth(&self) -> u64 {
+        if self.input_closed.load(Ordering::SeqCst) {
+            return 0;
+        }
+        let received = self.received.load(Ordering::Relaxed);
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        self.sent.load(Ordering::Relaxed).saturating_sub(received + dropped)
+    }
+
+    /// Free the room of all items, as the input port has been dropped.
+    fn release_all(&self) {
+        if let Some(budget) = self.budget.get() {
+            let bytes = self.buffered_bytes.swap(0, Ordering::SeqCst);
+            budget.buffered.fetch_sub(bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Handle to query the statistics of a single connection, see `Output::connect_tracked`.
+///
+/// Cheap to clone and usable from any thread, also after the connection has been closed.
+#[derive(Debug, Clone)]
+pub struct ConnectionHandle {
+    id: ConnectionId,
+    stats: Arc<ConnectionStats>,
+}
+
+impl ConnectionHandle {
+    /// Id of the connection, as returned by `Output::connect`.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Number of items written to the connection since its creation.
+    pub fn sent(&self) -> u64 {
+        self.stats.sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of items read from the connection by its input port since its creation.
+    pub fn received(&self) -> u64 {
+        self.stats.received.load(Ordering::Relaxed)
+    }
+
+    /// Number of items lost since creation, because the input port had been dropped,
+    /// discarded them with `Input::clear` or rejected them with a filter.
+    pub fn dropped(&self) -> u64 {
+        self.stats.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the sequence numbers read by the input port skipped ahead since creation.
+    ///
+    /// Each gap means that one or more items have been lost before reaching the channel,
+    /// e.g. dropped by `Output::fire` on a full bounded connection (see
+    /// `Output::connect_bounded`), see `Sequenced`. Items discarded by the input port itself
+    /// count as dropped, not as gaps.
+    pub fn gaps(&self) -> u64 {
+        self.stats.gaps.load(Ordering::Relaxed)
+    }
+
+    /// Number of items currently buffered in the connection.
+    ///
+    /// Items still buffered when the input port is dropped count as dropped, so the depth of a
+    /// connection without input port is 0.
+    pub fn depth(&self) -> u64 {
+        self.stats.depth()
+    }
+
+    /// Total time spent writing to the channel of the connection since its creation.
+    ///
+    /// Measures the channel operation only, not cloning or tracing the data. Time growing
+    /// faster than the number of items sent hints at contention with the receiving side.
+    /// Measuring costs two clock reads per item and side, so it is only available with the
+    /// `contention` feature.
+    #[cfg(feature = "contention")]
+    pub fn send_time(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.stats.channel_nanos[ChannelOp::Send as usize].load(Ordering::Relaxed))
+    }
+
+    /// Total time spent reading from the channel of the connection since its creation,
+    /// including attempts that found it empty. See `Self::send_time`.
+    #[cfg(feature = "contention")]
+    pub fn recv_time(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.stats.channel_nanos[ChannelOp::Recv as usize].load(Ordering::Relaxed))
+    }
+
+    /// Number of times `Output::fire_blocking` found the connection full and had to wait.
+    #[cfg(feature = "contention")]
+    pub fn send_retries(&self) -> u64 {
+        self.stats.send_retries.load(Ordering::Relaxed)
+    }
+}
+
+/// Switches a connection made by `Output::connect_gated` on and off.
+///
+/// While inactive, the output skips the connection: data fired in the meantime is not sent, not
+/// counted and does not cause gaps in the sequence numbers (see `Input::fetch_sequenced`). Data
+/// sent before stays buffered. The handle is cheap to clone and usable from any thread, e.g.
+/// by a node of another region or a supervising thread. A switch takes effect with the next
+/// `fire`, a concurrent `fire` sees either state.
+#[derive(Debug, Clone)]
+pub struct GatedConnection {
+    id: ConnectionId,
+    gate: Arc<AtomicBool>,
+}
+
+impl GatedConnection {
+    /// Id of the connection, e.g. for `Output::fire_to`.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Send data fired from now on if `active` is true, skip it otherwise.
+    pub fn set_active(&self, active: bool) {
+        self.gate.store(active, Ordering::Relaxed);
+    }
+
+    /// Whether data fired now is sent over the connection.
+    pub fn is_active(&self) -> bool {
+        self.gate.load(Ordering::Relaxed)
+    }
+}
+
+/// Name of a connection given by `Output::connect_labeled`, see `Input::disconnected_sources`.
+pub type Label = String;
+
+/// Channel of a receiving end.
+pub(crate) enum SourceRx<T> {
+    Envelopes(Receiver<Envelope<T>>),
+    /// Channel of an external producer with the sequence number of the next item, see
+    /// `Input::connect_receiver`
+    Bridge(Receiver<T>, Cell<u64>),
+}
+
+impl<T> SourceRx<T> {
+    fn try_recv(&self) -> Result<Envelope<T>, TryRecvError> {
+        match self {
+            Self::Envelopes(rx) => rx.try_recv(),
+            Self::Bridge(rx, next_seq) => {
+                let data = rx.try_recv()?;
+                let seq = next_seq.get();
+                next_seq.set(seq + 1);
+                Ok(Envelope {
+                    seq,
+                    stamp: next_stamp(),
+                    data,
+                })
+            }
+        }
+    }
+}
+
+/// Receiving end of a connection.
+pub(crate) struct Source<T> {
+    pub(crate) id: ConnectionId,
+    pub(crate) rx: SourceRx<T>,
+    pub(crate) stats: Arc<ConnectionStats>,
+    /// See `Output::connect_labeled`
+    pub(crate) label: Option<Label>,
+    /// Item read by `Self::is_drained`, handed out before those of `rx`
+    pub(crate) peeked: Cell<Option<Envelope<T>>>,
+}
+
+impl<T> Source<T> {
+    /// Whether the sending end has been dropped and all data it sent has been read.
+    fn is_drained(&self) -> bool {
+        // Pairs with the store in `Sink::drop`, so all items sent before are counted. Bridges
+        // have no `Sink`, their channel tells below whether the producer is gone.
+        if !self.stats.closed.load(Ordering::Acquire) && !matches!(self.rx, SourceRx::Bridge(..)) {
+            return false;
+        }
+        let sent = self.stats.sent.load(Ordering::Acquire);
+        let read = self.stats.received.load(Ordering::Acquire) + self.stats.dropped.load(Ordering::Acquire);
+        if sent != read {
+            return false;
+        }
+        // The counters may miss items, e.g. of adapted connections, so also check the channel.
+        match self.try_recv() {
+            Ok(envelope) => {
+                self.peeked.set(Some(envelope));
+                false
+            }
+            Err(e) => e == TryRecvError::Disconnected,
+        }
+    }
+
+    /// Read the next item of the channel, the peeked one first.
+    fn try_recv(&self) -> Result<Envelope<T>, TryRecvError> {
+        match self.peeked.take() {
+            Some(envelope) => Ok(envelope),
+            None => {
+                let result = self.rx.try_recv();
+                if matches!(result, Err(TryRecvError::Disconnected)) && matches!(self.rx, SourceRx::Bridge(..)) {
+                    // The external producer has no `Sink` to mark the connection closed.
+                    self.stats.closed.store(true, Ordering::Release);
+                }
+                result
+            }
+        }
+    }
+
+    /// Label of the connection, its id if it has none.
+    fn label(&self) -> Label {
+        self.label.clone().unwrap_or_else(|| self.id.to_string())
+    }
+
+    /// Read the next item that passes the filters of the port and the current region, if any.
+    ///
+    /// Rejected items are counted as dropped.
+    fn recv(&self, filter: &mut Option<PortFilter<T>>) -> Result<Envelope<T>, TryRecvError>
+    where
+        T: 'static,
+    {
+        loop {
+            let envelope = timed(ChannelOp::Recv, &self.stats, || self.try_recv())?;
+            self.stats.release();
+            self.stats.check_seq(self.id, envelope.seq);
+            if matches!(self.rx, SourceRx::Bridge(..)) {
+                // Not counted by the external producer
+                self.stats.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            if accepts(&envelope.data, filter) {
+                self.stats.received.fetch_add(1, Ordering::Relaxed);
+                return Ok(envelope);
+            }
+            log::debug!("Filter rejected item {} of connection {}", envelope.seq, self.id);
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Discard all buffered items.
+    fn clear(&self) {
+        while let Ok(envelope) = self.try_recv() {
+            self.stats.release();
+            self.stats.check_seq(self.id, envelope.seq);
+            if matches!(self.rx, SourceRx::Bridge(..)) {
+                self.stats.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Drop for Source<T> {
+    fn drop(&mut self) {
+        self.stats.input_closed.store(true, Ordering::SeqCst);
+        // Count what nobody will read anymore.
+        self.clear();
+        self.stats.release_all();
+    }
+}
+
+impl<T> Drop for Sink<T> {
+    fn drop(&mut self) {
+        OPEN_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+        self.stats.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Channel of a sending end.
+pub(crate) enum SinkTx<T> {
+    Unbounded(Sender<Envelope<T>>),
+    /// See `Output::connect_bounded`
+    Bounded(SyncSender<Envelope<T>>),
+    /// Channel of an external consumer, see `Output::connect_sender`
+    Bridge(Sender<T>),
+    /// Converts the data and sends it to a channel of another type, returns `false` if the
+    /// receiving end has been dropped, see `Output::connect_with`
+    Adapted(Box<dyn FnMut(Envelope<T>) -> bool + Send>),
+}
+
+/// Sending end of a connection.
+pub(crate) struct Sink<T> {
+    pub(crate) id: ConnectionId,
+    pub(crate) tx: SinkTx<T>,
+    /// Sequence number of the next message
+    pub(crate) next_seq: u64,
+    pub(crate) stats: Arc<ConnectionStats>,
+    /// Whether data is sent, see `Output::connect_gated`
+    gate: Option<Arc<AtomicBool>>,
+}
+
+impl<T> Sink<T> {
+    pub(crate) fn new(id: ConnectionId, tx: SinkTx<T>, stats: Arc<ConnectionStats>) -> Self {
+        OPEN_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        Self {
+            id,
+            tx,
+            next_seq: 0,
+            stats,
+            gate: None,
+        }
+    }
+
+    /// Whether data fired on the output is sent over this connection, see `GatedConnection`.
+    fn is_active(&self) -> bool {
+        self.gate.as_ref().is_none_or(|g| g.load(Ordering::Relaxed))
+    }
+
+    /// Handle to the statistics of this connection.
+    pub(crate) fn handle(&self) -> ConnectionHandle {
+        ConnectionHandle {
+            id: self.id,
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Whether the receiving end has been dropped.
+    fn is_input_closed(&self) -> bool {
+        self.stats.input_closed.load(Ordering::Relaxed)
+    }
+
+    /// Assign the next sequence number to `data`.
+    ///
+    /// Numbers are assigned even if the data is dropped afterwards, so the receiver sees a gap.
+    fn envelope(&mut self, data: T) -> Envelope<T> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        Envelope {
+            seq,
+            stamp: next_stamp(),
+            data,
+        }
+    }
+
+    /// Send `envelope` without waiting, `SendError::Full` also if the memory budget is
+    /// exhausted.
+    fn try_send(&mut self, envelope: Envelope<T>) -> Result<(), SendError<T>> {
+        if !self.stats.reserve() {
+            return Err(SendError::Full(envelope));
+        }
+        let result = self.send_reserved(envelope);
+        match &result {
+            Err(_) => self.stats.release(),
+            // Buffered after the input port has been dropped, nobody reads it anymore.
+            Ok(()) if self.stats.input_closed.load(Ordering::SeqCst) => self.stats.release_all(),
+            Ok(()) => {}
+        }
+        result
+    }
+
+    fn send_reserved(&mut self, envelope: Envelope<T>) -> Result<(), SendError<T>> {
+        timed(ChannelOp::Send, &self.stats, || match &mut self.tx {
+            SinkTx::Unbounded(tx) => tx.send(envelope).map_err(|_| SendError::Disconnected),
+            SinkTx::Bounded(tx) => tx.try_send(envelope).map_err(|e| match e {
+                TrySendError::Full(envelope) => SendError::Full(envelope),
+                TrySendError::Disconnected(_) => SendError::Disconnected,
+            }),
+            SinkTx::Bridge(tx) => tx.send(envelope.data).map_err(|_| SendError::Disconnected),
+            SinkTx::Adapted(send) => send(envelope).then_some(()).ok_or(SendError::Disconnected),
+        })
+    }
+
+    /// Send `data`, returns `false` if the receiving end has been dropped.
+    ///
+    /// Drops `data` if the connection is bounded and full.
+    pub(crate) fn send(&mut self, data: T) -> bool {
+        let envelope = self.envelope(data);
+        match self.try_send(envelope) {
+            Ok(()) => true,
+            Err(SendError::Full(_)) => {
+                log::debug!("Connection {} or the memory budget is full, dropping data", self.id);
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(SendError::Disconnected) => {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Send `data`, waiting for space in a bounded connection.
+    ///
+    /// Returns `Ok(false)` if the receiving end has been dropped.
+    fn send_blocking(&mut self, data: T, shutdown: &ShutdownToken) -> Result<bool, FireError> {
+        let mut envelope = self.envelope(data);
+        loop {
+            match self.try_send(envelope) {
+                Ok(()) => return Ok(true),
+                Err(SendError::Full(e)) => envelope = e,
+                Err(SendError::Disconnected) => {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(false);
+                }
+            }
+            #[cfg(feature = "contention")]
+            self.stats.send_retries.fetch_add(1, Ordering::Relaxed);
+            if shutdown.is_shutting_down() {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(FireError::ShuttingDown);
+            }
+            crate::group::pause_node_call(|| std::thread::sleep(BLOCKING_POLL_INTERVAL));
+        }
+    }
+}
+
+/// Failure of `Sink::try_send`.
+enum SendError<T> {
+    /// The connection or the memory budget is full, the envelope can be sent again.
+    Full(Envelope<T>),
+    /// The receiving end has been dropped.
+    Disconnected,
+}
+
+/// Interval in which `Output::fire_blocking` retries full connections.
+const BLOCKING_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Error of `Output::fire_blocking`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FireError {
+    /// The infrastructure is shutting down, the data has not been delivered to all connections.
+    ShuttingDown,
+}
+
+/// Buffering of a connection between regions, see `InfrastructureBuilder::connect_with_policy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CrossRegionPolicy {
+    /// Buffer any number of items, see `Output::connect`. Grows without bound if the consumer
+    /// cannot keep up.
+    #[default]
+    Unbounded,
+    /// Buffer at most this many items and drop further ones, see `Output::connect_bounded`.
+    Bounded(usize),
+    /// Like `Bounded`, with the capacity derived from the ticks of both regions, see
+    /// `Self::suggested_capacity`.
+    Auto,
+}
+
+impl CrossRegionPolicy {
+    /// Consumer ticks of data the capacity suggested by `Self::suggested_capacity` can hold.
+    pub const AUTO_SLACK: u32 = 4;
+
+    /// Capacity for a connection from a region with tick `producer_tick` to one with tick
+    /// `consumer_tick`.
+    ///
+    /// Assumes one item per producer tick, so it holds the items fired during `AUTO_SLACK`
+    /// consumer ticks: from a fast to a slow region, this is the ratio of the ticks times the
+    /// slack, from a slow to a fast region just the slack.
+    pub fn suggested_capacity(producer_tick: std::time::Duration, consumer_tick: std::time::Duration) -> usize {
+        let producer = producer_tick.as_nanos().max(1);
+        let per_consumer_tick = consumer_tick.as_nanos().div_ceil(producer).max(1);
+        let capacity = per_consumer_tick.saturating_mul(u128::from(Self::AUTO_SLACK));
+        usize::try_from(capacity).unwrap_or(usize::MAX)
+    }
+}
+
+/// Create a new connection.
+pub(crate) fn channel<T>() -> (Sink<T>, Source<T>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    connection(SinkTx::Unbounded(tx), rx)
+}
+
+/// Create a new connection buffering at most `capacity` items.
+pub(crate) fn bounded_channel<T>(capacity: usize) -> (Sink<T>, Source<T>) {
+    let (tx, rx) = std::sync::mpsc::sync_channel(capacity);
+    connection(SinkTx::Bounded(tx), rx)
+}
+
+/// Connect `tx` with `rx`, which carries data of another type for adapted connections.
+fn connection<T, U>(tx: SinkTx<T>, rx: Receiver<Envelope<U>>) -> (Sink<T>, Source<U>) {
+    let id = ConnectionId::next();
+    let stats = Arc::new(ConnectionStats {
+        item_size: std::mem::size_of::<Envelope<U>>() as u64,
+        ..Default::default()
+    });
+    (
+        Sink::new(id, tx, stats.clone()),
+        Source {
+            id,
+            rx: SourceRx::Envelopes(rx),
+            stats,
+            label: None,
+            peeked: Cell::new(None),
+        },
+    )
+}
+
 /// Input port.
 ///
 /// Used to receive data of type `T`.
 pub struct Input<T> {
-    rx: Vec<std::sync::mpsc::Receiver<T>>,
+    pub(crate) sources: Vec<Source<T>>,
+    /// Number of items received since creation
+    received: u64,
+    /// Connection `try_fetch_one` starts with
+    next_source: usize,
+    /// See `Self::trace_with_debug`
+    debug: TraceFormatter<T>,
+    /// Labels of removed connections, see `Self::disconnected_sources`
+    disconnected: Vec<Label>,
+    /// Capacity `fetch` reserves for its result, see `Self::with_capacity_hint`
+    capacity_hint: usize,
+    /// See `Self::with_filter`
+    filter: Option<PortFilter<T>>,
+    /// Keeps listeners of remote connections alive.
+    #[cfg(feature = "network")]
+    pub(crate) remote_guards: Vec<std::sync::Arc<()>>,
 }
 
-impl<T> Input<T> {
-    pub fn fetch(&mut self) -> Vec<T> {
-        let mut ret = Vec::new();
-        for r in &mut self.rx {
-            'read_empty: loop {
-                match r.try_recv() {
-                    Ok(data) => ret.push(data),
-                    Err(_) => break 'read_empty,
-                }
+impl<T> Default for Input<T> {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            received: 0,
+            next_source: 0,
+            debug: None,
+            disconnected: Vec::new(),
+            capacity_hint: 0,
+            filter: None,
+            #[cfg(feature = "network")]
+            remote_guards: Vec::new(),
+        }
+    }
+}
+
+impl<T: 'static> Input<T> {
+    /// Create a port whose `fetch` reserves room for `capacity` items up front.
+    ///
+    /// Avoids repeated reallocations of the returned `Vec` if the typical number of items per
+    /// tick is known, e.g. for batched producers. Fetching more items still works.
+    pub fn with_capacity_hint(capacity: usize) -> Self {
+        Self {
+            capacity_hint: capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Discard all received data for which `filter` returns `false`.
+    ///
+    /// The filter runs on each item when it is fetched, before the data reaches the node.
+    /// Rejected items are logged at debug level and counted as dropped by their connection (see
+    /// `ConnectionHandle::dropped`), they neither count as received nor towards the per-tick limit
+    /// of the node. Use it to validate data at the boundary of a region, see also
+    /// `RegionBuilder::with_inbound_filter`. Filters cannot modify the data, transform it in the
+    /// node instead.
+    pub fn with_filter(mut self, filter: impl FnMut(&T) -> bool + Send + 'static) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Enable `Self::fetch_time_ordered`, before any data is sent to this port.
+    ///
+    /// Items are only stamped with their send time once a port of the process enabled it, as
+    /// stamping costs an increment of a process-wide counter for each item sent on any port
+    /// from then on.
+    pub fn with_time_order(self) -> Self {
+        enable_time_order();
+        self
+    }
+
+    /// Create a port receiving the data sent to `rx`, see `Self::connect_receiver`.
+    pub fn from_receiver(rx: Receiver<T>) -> Self {
+        let mut input = Self::default();
+        input.connect_receiver(rx);
+        input
+    }
+
+    /// Receive the data an external producer sends to `rx`, e.g. to feed a channel of existing
+    /// code into the graph.
+    ///
+    /// The channel becomes a regular connection of this port. Its sequence numbers and logical
+    /// send time (see `Self::fetch_sequenced` and `Self::fetch_time_ordered`) are assigned when
+    /// an item is fetched. The port owns `rx`: the connection closes like one of a dropped output
+    /// port once all senders of the channel are dropped and the buffered data has been read,
+    /// and dropping the port makes further sends on the channel fail.
+    pub fn connect_receiver(&mut self, rx: Receiver<T>) -> ConnectionId {
+        let id = ConnectionId::next();
+        self.sources.push(Source {
+            id,
+            rx: SourceRx::Bridge(rx, Cell::new(0)),
+            stats: Default::default(),
+            label: None,
+            peeked: Cell::new(None),
+        });
+        id
+    }
+
+    /// Read all data that is currently buffered.
+    ///
+    /// Connections whose output port has been dropped are removed once all data they delivered
+    /// has been read.
+    ///
+    /// If the node owning this port limits its per-tick consumption (see `Node::max_items_per_tick`),
+    /// at most the remaining budget is returned. Items exceeding it stay buffered in the
+    /// connection and are returned by the next call.
+    /// Connections are read in the order they were made.
+    pub fn fetch(&mut self) -> Vec<T> {
+        let mut ret = self.result_vec();
+        self.drain(|_, envelope| ret.push(envelope.data));
+        ret
+    }
+
+    /// Like `fetch`, but also return the connection and sequence number of each item.
+    ///
+    /// Allows detecting lost messages, see `Sequenced`.
+    pub fn fetch_sequenced(&mut self) -> Vec<Sequenced<T>> {
+        let mut ret = self.result_vec();
+        self.drain(|connection, envelope| {
+            ret.push(Sequenced {
+                connection,
+                seq: envelope.seq,
+                data: envelope.data,
+            })
+        });
+        ret
+    }
+
+    /// Like `fetch`, but return the items in the order they have been sent, across all
+    /// connections.
+    ///
+    /// `fetch` returns the items of one connection after the other, so the interleaving of
+    /// several producers is lost. Here, the items are sorted by their logical send time, a
+    /// process-wide counter stamped on each item when it is written to a connection. Items of
+    /// remote connections are stamped when they arrive in this process, items spilled
+    /// to disk (see `spill::SpillOutput`) when they are moved back into memory.
+    ///
+    /// Stamping has to be enabled with `Self::with_time_order`. Otherwise it is enabled by the
+    /// first call, with a warning, and items sent before are returned first, in the order of
+    /// `fetch`.
+    ///
+    /// # Overhead
+    ///
+    /// See `Self::with_time_order` for the cost of stamping. Fetched items are sorted with a
+    /// stable sort, which is linear for a single connection, as the items of each connection
+    /// are already in order.
+    pub fn fetch_time_ordered(&mut self) -> Vec<T> {
+        if !TIME_ORDER.load(Ordering::Relaxed) {
+            log::warn!("Time order has not been enabled, see `Input::with_time_order`");
+            enable_time_order();
+        }
+        let mut stamped = self.result_vec();
+        self.drain(|_, envelope| stamped.push((envelope.stamp, envelope.data)));
+        stamped.sort_by_key(|(stamp, _)| *stamp);
+        stamped.into_iter().map(|(_, data)| data).collect()
+    }
+
+    /// Read a single buffered item, if any.
+    ///
+    /// Connections are tried in turn, starting with the one after the connection that delivered
+    /// the previous item, so a busy connection cannot starve the others. Counts towards the
+    /// per-tick limit of the node like `fetch`, see `Node::max_items_per_tick`.
+    pub fn try_fetch_one(&mut self) -> Option<T> {
+        if FETCH_BUDGET.with(Cell::get) == Some(0) {
+            return None;
+        }
+        let mut tried = 0;
+        while tried < self.sources.len() {
+            let index = self.next_source % self.sources.len();
+            match self.sources[index].recv(&mut self.filter) {
+                Ok(envelope) => {
+                    trace("fetch", self.sources[index].id, &envelope.data, self.debug);
+                    self.next_source = index + 1;
+                    FETCH_BUDGET.with(|b| b.set(b.get().map(|b| b - 1)));
+                    RECEIVED.with(|r| r.set(r.get() + 1));
+                    self.received += 1;
+                    return Some(envelope.data);
+                }
+                Err(TryRecvError::Empty) => {
+                    self.next_source = index + 1;
+                    tried += 1;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    log::debug!("Removing connection {} from dropped output port", self.sources[index].id);
+                    let source = self.sources.remove(index);
+                    self.disconnected.push(source.label());
+                    self.next_source = index;
+                }
+            }
+        }
+        None
+    }
+
+    /// Empty result of a fetch, with room for the expected number of items.
+    fn result_vec<U>(&self) -> Vec<U> {
+        let budget = FETCH_BUDGET.with(Cell::get).unwrap_or(usize::MAX);
+        Vec::with_capacity(self.capacity_hint.min(budget))
+    }
+
+    /// Pass all buffered data to `f`, honoring the fetch budget of the current node.
+    fn drain(&mut self, mut f: impl FnMut(ConnectionId, Envelope<T>)) {
+        let mut budget = FETCH_BUDGET.with(Cell::get);
+        let mut count: u64 = 0;
+        let debug = self.debug;
+        let disconnected = &mut self.disconnected;
+        let filter = &mut self.filter;
+        self.sources.retain(|source| {
+            loop {
+                if budget == Some(0) {
+                    return true;
+                }
+                match source.recv(filter) {
+                    Ok(envelope) => {
+                        trace("fetch", source.id, &envelope.data, debug);
+                        f(source.id, envelope);
+                        count += 1;
+                        if let Some(b) = &mut budget {
+                            *b -= 1;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => return true,
+                    Err(TryRecvError::Disconnected) => {
+                        log::debug!("Removing connection {} from dropped output port", source.id);
+                        disconnected.push(source.label());
+                        return false;
+                    }
+                }
+            }
+        });
+        FETCH_BUDGET.with(|b| b.set(budget));
+        RECEIVED.with(|r| r.set(r.get() + count));
+        self.received += count;
+    }
+
+    /// Like `drain`, but take up to `batch` items of each connection in turn, see `FairInput`.
+    ///
+    /// Starts with the connection after the one that was read last.
+    fn drain_round_robin(&mut self, batch: usize, mut f: impl FnMut(ConnectionId, Envelope<T>)) {
+        let mut budget = FETCH_BUDGET.with(Cell::get);
+        let mut count: u64 = 0;
+        let mut index = self.next_source;
+        // Connections in a row that had no data
+        let mut empty = 0;
+        while !self.sources.is_empty() && empty < self.sources.len() && budget != Some(0) {
+            index %= self.sources.len();
+            let mut taken = 0;
+            let mut removed = false;
+            while taken < batch && budget != Some(0) {
+                let source = &self.sources[index];
+                match source.recv(&mut self.filter) {
+                    Ok(envelope) => {
+                        trace("fetch", source.id, &envelope.data, self.debug);
+                        f(source.id, envelope);
+                        taken += 1;
+                        if let Some(b) = &mut budget {
+                            *b -= 1;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        log::debug!("Removing connection {} from dropped output port", source.id);
+                        let source = self.sources.remove(index);
+                        self.disconnected.push(source.label());
+                        removed = true;
+                        break;
+                    }
+                }
+            }
+            count += taken as u64;
+            empty = if taken == 0 { empty + 1 } else { 0 };
+            if !removed {
+                index += 1;
+            }
+        }
+        self.next_source = index;
+        FETCH_BUDGET.with(|b| b.set(budget));
+        RECEIVED.with(|r| r.set(r.get() + count));
+        self.received += count;
+    }
+
+    /// Discard all data that is currently buffered.
+    ///
+    /// Unlike `fetch`, discarded data neither counts as received nor towards the per-tick limit
+    /// of the node.
+    pub fn clear(&mut self) {
+        for source in &self.sources {
+            source.clear();
+        }
+    }
+
+    /// Labels of the connections whose output port has been dropped since the last call.
+    ///
+    /// A connection counts as disconnected once all data it delivered has been read, so a node
+    /// aggregating from a varying set of producers can stop expecting data from it. The
+    /// connection is removed from this port. Connections without label (see
+    /// `Output::connect_labeled`) are reported with their `ConnectionId` as label, e.g. `#3`.
+    pub fn disconnected_sources(&mut self) -> Vec<Label> {
+        let disconnected = &mut self.disconnected;
+        self.sources.retain(|source| {
+            let gone = source.is_drained();
+            if gone {
+                log::debug!("Removing connection {} from dropped output port", source.id);
+                disconnected.push(source.label());
             }
-        }
-        ret
+            !gone
+        });
+        std::mem::take(&mut self.disconnected)
+    }
+
+    /// Number of items this port received since its creation.
+    pub fn received_count(&self) -> u64 {
+        self.received
+    }
+
+    /// Number of connections of this port, e.g. to check the wiring in tests.
+    ///
+    /// Connections whose output port has been dropped are counted until all data they
+    /// delivered has been read, see `Self::is_exhausted`. See `Output::connection_count` for
+    /// the other side.
+    ///
+    /// ```
+    /// let mut first = flexcore::Output::<f64>::default();
+    /// let mut second = flexcore::Output::<f64>::default();
+    /// let mut input = flexcore::Input::<f64>::default();
+    /// first.connect(&mut input);
+    /// second.connect(&mut input);
+    /// assert_eq!(input.source_count(), 2);
+    /// assert_eq!(first.connection_count(), 1);
+    /// ```
+    pub fn source_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Render received data with `Debug` in message traces instead of just its type and size.
+    ///
+    /// See `InfrastructureBuilder::with_message_tracing`.
+    pub fn trace_with_debug(&mut self)
+    where
+        T: std::fmt::Debug,
+    {
+        self.debug = Some(format_debug::<T>);
+    }
+
+    /// Whether no more data can arrive on this port.
+    ///
+    /// True once the output ports of all connections have been dropped, e.g. because their region
+    /// finished, and all data they delivered has been read by `fetch`. A port without any
+    /// connections is exhausted as well.
+    pub fn is_exhausted(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Number of items waiting in the connections of this port, see `ConnectionHandle::depth`.
+    ///
+    /// Items of a channel of an external producer (see `Self::connect_receiver`) are only
+    /// counted once they are fetched, so they are not included.
+    pub fn buffered_count(&self) -> u64 {
+        self.sources.iter().map(|s| s.stats.depth()).sum()
     }
 }
 
-#[derive(Default)]
 /// Output port.
 /// 
 /// Used to send data of type `T`.
 pub struct Output<T: Clone> {
-    tx: Vec<std::sync::mpsc::Sender<T>>,
+    pub(crate) sinks: Vec<Sink<T>>,
+    /// Channels of `subscribe`
+    observers: Vec<Sender<T>>,
+    /// See `Self::trace_with_debug`
+    debug: TraceFormatter<T>,
+    /// Number of `fire` calls since creation
+    fired: u64,
+    /// Number of connections removed because their input port has been dropped
+    closed: usize,
+    /// Topics this port publishes to, see `Self::publish`
+    pub(crate) topics: Vec<Subscribers<T>>,
+}
+
+impl<T: Clone> Default for Output<T> {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            observers: Vec::new(),
+            debug: None,
+            fired: 0,
+            closed: 0,
+            topics: Vec::new(),
+        }
+    }
 }
 
 impl<T: Clone> Output<T> {
     /// Connect this output to a compatible input source.
     /// 
     /// It will send its data to the specified input port.
-    pub fn connect(&mut self, input: &mut Input<T>) {
+    ///
+    /// Both ports carry the same type `T`, so connecting mismatching ports is a compile error:
+    ///
+    /// ```compile_fail
+    /// let mut output = flexcore::Output::<f64>::default();
+    /// let mut input = flexcore::Input::<u8>::default();
+    /// output.connect(&mut input);
+    /// ```
+    pub fn connect(&mut self, input: &mut Input<T>) -> ConnectionId {
+        let (sink, source) = channel();
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Observe all data written to this port from now on, e.g. to check it in tests.
+    ///
+    /// Unlike `connect`, this does not need an `Input` port and is not counted as a connection.
+    /// The observer gets a clone of everything passed to `fire` and `fire_to`. It is removed once
+    /// the returned receiver is dropped.
+    pub fn subscribe(&mut self) -> Receiver<T> {
         let (tx, rx) = std::sync::mpsc::channel();
-        self.tx.push(tx);
-        input.rx.push(rx);
+        self.observers.push(tx);
+        rx
+    }
+
+    /// Send a clone of `t` to all observers, removing those that are gone.
+    fn notify(&mut self, t: &T) {
+        self.observers.retain(|observer| observer.send(t.clone()).is_ok());
+    }
+
+    /// Deliver `t` to the subscribers of all topics of this port, see `Self::publish`.
+    fn publish_topics(&self, t: &T) {
+        for topic in &self.topics {
+            bus::publish(topic, t);
+        }
+    }
+
+    /// Like `connect`, but name the connection `label`.
+    ///
+    /// The input port reports the label once the connection closes, see
+    /// `Input::disconnected_sources`.
+    pub fn connect_labeled(&mut self, input: &mut Input<T>, label: impl Into<Label>) -> ConnectionId {
+        let (sink, mut source) = channel();
+        source.label = Some(label.into());
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Connect this output to each of `inputs`, see `connect`.
+    ///
+    /// Returns the ids of the new connections in the order of `inputs`. The connections behave
+    /// exactly like ones made one by one, e.g. `fire` sends to all of them.
+    pub fn connect_all(&mut self, inputs: &mut [&mut Input<T>]) -> Vec<ConnectionId> {
+        inputs.iter_mut().map(|input| self.connect(input)).collect()
+    }
+
+    /// Like `connect`, but the connection buffers at most `capacity` items.
+    ///
+    /// `fire` drops data that does not fit anymore, the receiver sees a gap in the sequence
+    /// numbers (see `Input::fetch_sequenced`). `fire_blocking` waits for space instead.
+    /// A capacity of 0 is treated as 1.
+    pub fn connect_bounded(&mut self, input: &mut Input<T>, capacity: usize) -> ConnectionId {
+        let (sink, source) = bounded_channel(capacity.max(1));
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Like `connect`, but also return a handle to the statistics of the new connection.
+    pub fn connect_tracked(&mut self, input: &mut Input<T>) -> ConnectionHandle {
+        let (sink, source) = channel();
+        let handle = sink.handle();
+        self.sinks.push(sink);
+        input.sources.push(source);
+        handle
+    }
+
+    /// Like `connect`, but the connection only transfers data while it is active, e.g. to a
+    /// backup consumer during failover.
+    ///
+    /// The connection starts active if `active` is true. Switch it with the returned handle,
+    /// see `GatedConnection`.
+    pub fn connect_gated(&mut self, input: &mut Input<T>, active: bool) -> GatedConnection {
+        let (mut sink, source) = channel();
+        let gate = Arc::new(AtomicBool::new(active));
+        sink.gate = Some(gate.clone());
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        GatedConnection { id, gate }
+    }
+
+    /// Like `connect`, but convert the data with `f` for an input port of another type, e.g. to
+    /// pass on a single field without a separate node.
+    ///
+    /// `f` runs in `fire` on the producer side, once for each connection made this way, on a
+    /// clone of the data like for other connections. Any number of converting connections,
+    /// also to different types, can be made next to plain ones.
+    pub fn connect_with<U: Send + 'static>(
+        &mut self,
+        input: &mut Input<U>,
+        mut f: impl FnMut(T) -> U + Send + 'static,
+    ) -> ConnectionId
+    where
+        T: 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel::<Envelope<U>>();
+        let send = move |envelope: Envelope<T>| {
+            tx.send(Envelope {
+                seq: envelope.seq,
+                stamp: envelope.stamp,
+                data: f(envelope.data),
+            })
+            .is_ok()
+        };
+        let (sink, source) = connection(SinkTx::Adapted(Box::new(send)), rx);
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Send all data fired on this port to `tx`, e.g. to consume it outside of the graph.
+    ///
+    /// Unlike `Self::subscribe`, this feeds an existing channel and is a regular connection: it
+    /// is counted by `Self::connection_count` and addressed by `Self::fire_to` with the returned
+    /// id. The port owns `tx` until the receiver of the channel is dropped, which closes the
+    /// connection like dropping a connected input port. Other senders of the channel are not
+    /// affected, so the receiver may get data of several outputs and external producers.
+    pub fn connect_sender(&mut self, tx: Sender<T>) -> ConnectionId {
+        let id = ConnectionId::next();
+        self.sinks.push(Sink::new(id, SinkTx::Bridge(tx), Default::default()));
+        id
+    }
+
+    /// Render sent data with `Debug` in message traces instead of just its type and size.
+    ///
+    /// See `InfrastructureBuilder::with_message_tracing`.
+    pub fn trace_with_debug(&mut self)
+    where
+        T: std::fmt::Debug,
+    {
+        self.debug = Some(format_debug::<T>);
+    }
+
+    /// Number of `fire` calls on this port since its creation.
+    pub fn fired_count(&self) -> u64 {
+        self.fired
+    }
+
+    /// Number of connections of this port.
+    ///
+    /// Connections whose input port has been dropped are counted until the next `fire`.
+    pub fn connection_count(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Whether this port has been connected, but the input ports of all its connections have
+    /// been dropped, e.g. to stop producing once all consumers are gone.
+    ///
+    /// False for a port that has never been connected. Observers of `subscribe` do not count as
+    /// consumers, remote connections always count as connected.
+    pub fn is_closed(&self) -> bool {
+        (self.closed > 0 || !self.sinks.is_empty()) && self.sinks.iter().all(|s| s.is_input_closed())
     }
 
     /// Write data to this port.
+    ///
+    /// `t` is cloned for all but the last active connection, which gets `t` itself.
+    ///
+    /// Connections whose input port has been dropped, e.g. because its node has been removed,
+    /// are removed.
+    pub fn fire(&mut self, t: T) {
+        count_fire();
+        self.fired += 1;
+        self.notify(&t);
+        self.publish_topics(&t);
+        let last = self.sinks.iter().rposition(Sink::is_active);
+        let mut t = Some(t);
+        let debug = self.debug;
+        let before = self.sinks.len();
+        let mut idx = 0;
+        self.sinks.retain_mut(|sink| {
+            idx += 1;
+            if !sink.is_active() {
+                return true;
+            }
+            let data = if Some(idx - 1) == last { t.take() } else { t.clone() };
+            let Some(data) = data else {
+                return true;
+            };
+            trace("fire", sink.id, &data, debug);
+            if !sink.send(data) {
+                log::debug!("Removing connection {} to dropped input port", sink.id);
+                return false;
+            }
+            true
+        });
+        self.closed += before - self.sinks.len();
+    }
+
+    /// Write all items of `batch` to this port, one after the other, see `fire`.
+    ///
+    /// Cheaper than calling `fire` for each item: the batch is delivered to one connection after
+    /// the other, and the last connection gets the items themselves instead of clones. So a
+    /// connection may receive the whole batch before the next one receives its first item.
+    pub fn fire_batch(&mut self, batch: impl IntoIterator<Item = T>) {
+        let batch: Vec<T> = batch.into_iter().collect();
+        for t in &batch {
+            count_fire();
+            self.notify(t);
+            self.publish_topics(t);
+        }
+        self.fired += batch.len() as u64;
+        let last = self.sinks.iter().rposition(Sink::is_active);
+        let mut batch = Some(batch);
+        let debug = self.debug;
+        let before = self.sinks.len();
+        let mut idx = 0;
+        self.sinks.retain_mut(|sink| {
+            idx += 1;
+            if !sink.is_active() {
+                return true;
+            }
+            let items = if Some(idx - 1) == last { batch.take() } else { batch.clone() };
+            for data in items.into_iter().flatten() {
+                trace("fire", sink.id, &data, debug);
+                if !sink.send(data) {
+                    log::debug!("Removing connection {} to dropped input port", sink.id);
+                    return false;
+                }
+            }
+            true
+        });
+        self.closed += before - self.sinks.len();
+    }
+
+    /// Like `fire`, but wait for space in full bounded connections (see `connect_bounded`) and
+    /// in the memory budget (see `InfrastructureBuilder::with_memory_budget`).
+    ///
+    /// Gives up once `shutdown` tells that the infrastructure is shutting down, so nodes blocked
+    /// on a full connection do not prevent it from stopping. Connections are served in order, so
+    /// on `FireError::ShuttingDown` the later ones have not received `t`.
+    pub fn fire_blocking(&mut self, t: T, shutdown: &ShutdownToken) -> Result<(), FireError> {
+        count_fire();
+        self.fired += 1;
+        self.notify(&t);
+        self.publish_topics(&t);
+        let mut result = Ok(());
+        let debug = self.debug;
+        let before = self.sinks.len();
+        self.sinks.retain_mut(|sink| {
+            if result.is_err() || !sink.is_active() {
+                return true;
+            }
+            trace("fire", sink.id, &t, debug);
+            match sink.send_blocking(t.clone(), shutdown) {
+                Ok(true) => true,
+                Ok(false) => {
+                    log::debug!("Removing connection {} to dropped input port", sink.id);
+                    false
+                }
+                Err(e) => {
+                    result = Err(e);
+                    true
+                }
+            }
+        });
+        self.closed += before - self.sinks.len();
+        result
+    }
+
+    /// Turn this port into one that converts `T` to `U` when firing, see `MappedOutput`.
+    ///
+    /// Lets a node keep its internal type and present a `U` port to the graph. Connections,
+    /// observers and topics already made on this port are carried over and keep receiving `T`,
+    /// connections made on the returned port receive `U`.
+    pub fn map<U: Clone>(self, f: impl FnMut(T) -> U + Send + 'static) -> MappedOutput<T, U>
+    where
+        T: Send + 'static,
+    {
+        let mut mapped = MappedOutput::new(f);
+        if !self.sinks.is_empty() || !self.observers.is_empty() || !self.topics.is_empty() {
+            mapped.unmapped = Some(Box::new(self));
+        }
+        mapped
+    }
+
+    /// Write data to the connections `ids` of this port only.
+    ///
+    /// Allows nodes to route data themselves. `ids` not belonging to this port are skipped with
+    /// a warning, connections whose input port has been dropped are removed. Observers and topics
+    /// of the port receive `t` regardless of `ids`, like with `fire`.
+    pub fn fire_to(&mut self, ids: &[ConnectionId], t: T) {
+        count_fire();
+        self.fired += 1;
+        self.notify(&t);
+        self.publish_topics(&t);
+        for id in ids {
+            if !self.sinks.iter().any(|sink| sink.id == *id) {
+                log::warn!("Output port has no connection {id}, skipping it");
+            }
+        }
+        let debug = self.debug;
+        let before = self.sinks.len();
+        self.sinks.retain_mut(|sink| {
+            if !ids.contains(&sink.id) || !sink.is_active() {
+                return true;
+            }
+            trace("fire", sink.id, &t, debug);
+            if !sink.send(t.clone()) {
+                log::debug!("Removing connection {} to dropped input port", sink.id);
+                return false;
+            }
+            true
+        });
+        self.closed += before - self.sinks.len();
+    }
+}
+
+/// Output port that is written with `T` but sends `U`, e.g. for unit conversions at the node
+/// boundary.
+///
+/// Created with `Output::map`. The conversion runs in `fire`, once per call, so connections are
+/// made with `Input<U>` ports and receive clones of the converted value.
+pub struct MappedOutput<T, U: Clone> {
+    output: Output<U>,
+    f: Box<dyn FnMut(T) -> U + Send>,
+    /// Port this one was mapped from, if it was in use, see `Output::map`
+    unmapped: Option<Box<dyn Unmapped<T>>>,
+}
+
+/// The `Output<T>` kept by a `MappedOutput`, without requiring `T: Clone` of the latter.
+trait Unmapped<T>: AnyOutput + Send {
+    fn fire_clone(&mut self, t: &T);
+}
+
+impl<T: Clone + Send + 'static> Unmapped<T> for Output<T> {
+    fn fire_clone(&mut self, t: &T) {
+        self.fire(t.clone());
+    }
+}
+
+impl<T, U: Clone> MappedOutput<T, U> {
+    /// Create an unconnected port converting with `f`, like `Output::map` of a new port.
+    pub fn new(f: impl FnMut(T) -> U + Send + 'static) -> Self {
+        Self {
+            output: Output::default(),
+            f: Box::new(f),
+            unmapped: None,
+        }
+    }
+
+    /// Connect this output to an input port of the converted type, see `Output::connect`.
+    pub fn connect(&mut self, input: &mut Input<U>) -> ConnectionId {
+        self.output.connect(input)
+    }
+
+    /// Convert `t` and write it to this port, see `Output::fire`.
+    ///
+    /// Connections carried over by `Output::map` receive a clone of `t` itself.
     pub fn fire(&mut self, t: T) {
-        for tx in &mut self.tx {
-            if let Err(e) = tx.send(t.clone()) {
-                log::error!("Could not send message: {e}");
+        if let Some(unmapped) = &mut self.unmapped {
+            unmapped.fire_clone(&t);
+        }
+        let u = (self.f)(t);
+        self.output.fire(u);
+    }
+
+    /// The underlying port sending converted data, e.g. for `Output::connect_bounded`.
+    pub fn output_mut(&mut self) -> &mut Output<U> {
+        &mut self.output
+    }
+}
+
+/// Input port that reads its connections in turn, so a busy producer cannot starve the others.
+///
+/// Connect outputs via `Self::input_mut`. Each connection buffers its items separately, `fetch`
+/// takes up to `batch` items (see `Self::with_batch`) of each connection in rotation until all
+/// are empty or the per-tick limit of the node is reached (see `Node::max_items_per_tick`).
+///
+/// # Ordering
+///
+/// Items of one connection are returned in the order they were sent. Items of different
+/// connections are interleaved in batches, their relative send order is not preserved, see
+/// `Input::fetch_time_ordered` for that.
+///
+/// # Starvation
+///
+/// Without a per-tick limit, all buffered items are returned, so no connection waits for
+/// longer than one tick. With a limit, each connection with data gets a turn at least every
+/// `batch * connections / limit` ticks, rounded up: the next call continues with the connection
+/// after the last one read. A connection that sends faster than its share of the limit builds up a
+/// backlog, unlike with `Input::fetch` it cannot delay the other connections.
+pub struct FairInput<T> {
+    input: Input<T>,
+    batch: usize,
+}
+
+impl<T> Default for FairInput<T> {
+    fn default() -> Self {
+        Self {
+            input: Input::default(),
+            batch: 1,
+        }
+    }
+}
+
+impl<T: 'static> FairInput<T> {
+    /// Create a port that takes up to `batch` items of each connection per turn.
+    ///
+    /// Larger batches reduce the overhead of switching between connections at the cost of
+    /// coarser interleaving. A batch of 0 is treated as 1. Defaults to 1.
+    pub fn with_batch(batch: usize) -> Self {
+        Self {
+            input: Input::default(),
+            batch: batch.max(1),
+        }
+    }
+
+    /// Read all data that is currently buffered, alternating between the connections.
+    pub fn fetch(&mut self) -> Vec<T> {
+        let mut ret = self.input.result_vec();
+        self.input.drain_round_robin(self.batch, |_, envelope| ret.push(envelope.data));
+        ret
+    }
+
+    /// The underlying port, e.g. for `Output::connect` or `Input::disconnected_sources`.
+    pub fn input_mut(&mut self) -> &mut Input<T> {
+        &mut self.input
+    }
+}
+
+impl<T: Send + 'static> AnyInput for FairInput<T> {
+    fn type_name(&self) -> &'static str {
+        self.input.type_name()
+    }
+
+    fn item_type_id(&self) -> TypeId {
+        self.input.item_type_id()
+    }
+
+    fn attach(&mut self, link: Box<dyn Any + Send>) -> Result<ConnectionId, FlexcoreError> {
+        self.input.attach(link)
+    }
+
+    fn connection_count(&self) -> usize {
+        self.input.connection_count()
+    }
+
+    fn buffered_count(&self) -> u64 {
+        self.input.buffered_count()
+    }
+
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        self.input.connection_ids()
+    }
+}
+
+/// Input ports of the same type addressed by index, e.g. one per sensor of a node averaging
+/// them.
+///
+/// Each slot is a separate `Input` with its own connections, connected with `Self::connect` or
+/// via `Self::slot_mut`. `fetch` returns the items of all slots tagged with the index of their
+/// slot. A slot without connections delivers nothing and is otherwise treated like the others,
+/// see `Self::unconnected_slots` to detect missing producers, e.g. in `Node::on_start`.
+///
+/// Slots are read in order of their index. If the node limits its per-tick consumption (see
+/// `Node::max_items_per_tick`) and the limit is reached, the next call starts with the slot
+/// after the last one read, so the first slots cannot starve the others.
+pub struct InputArray<T> {
+    slots: Vec<Input<T>>,
+    /// Slot the next `fetch` starts with
+    next: usize,
+}
+
+impl<T> Default for InputArray<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<T: 'static> InputArray<T> {
+    /// Create an array of `len` unconnected slots.
+    pub fn new(len: usize) -> Self {
+        Self {
+            slots: (0..len).map(|_| Input::default()).collect(),
+            next: 0,
+        }
+    }
+
+    /// Add an unconnected slot and return its index.
+    pub fn push(&mut self) -> usize {
+        self.slots.push(Input::default());
+        self.slots.len() - 1
+    }
+
+    /// Number of slots.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The slot `index`, `None` if out of range.
+    pub fn slot_mut(&mut self, index: usize) -> Option<&mut Input<T>> {
+        self.slots.get_mut(index)
+    }
+
+    /// Connect `output` to slot `index`, see `Output::connect`.
+    ///
+    /// Fails with `FlexcoreError::UnknownPort` if there is no such slot.
+    pub fn connect(&mut self, index: usize, output: &mut Output<T>) -> Result<ConnectionId, FlexcoreError>
+    where
+        T: Clone,
+    {
+        let len = self.slots.len();
+        let slot = self.slots.get_mut(index).ok_or_else(|| {
+            log::error!("Cannot connect slot {index} of input array with {len} slots");
+            FlexcoreError::UnknownPort
+        })?;
+        Ok(output.connect(slot))
+    }
+
+    /// Read all buffered data of all slots, tagged with the index of their slot.
+    ///
+    /// Items of a slot are returned together, in the order they were received.
+    pub fn fetch(&mut self) -> Vec<(usize, T)> {
+        let len = self.slots.len();
+        let mut items = Vec::new();
+        for offset in 0..len {
+            let index = (self.next + offset) % len;
+            items.extend(self.slots[index].fetch().into_iter().map(|t| (index, t)));
+            if FETCH_BUDGET.with(Cell::get) == Some(0) {
+                self.next = (index + 1) % len;
+                return items;
+            }
+        }
+        self.next = 0;
+        items
+    }
+
+    /// Like `Self::fetch`, but sorted by logical send time across all slots, see
+    /// `Input::fetch_time_ordered`, which also has to be enabled with `Input::with_time_order`.
+    pub fn fetch_time_ordered(&mut self) -> Vec<(usize, T)> {
+        if !TIME_ORDER.load(Ordering::Relaxed) {
+            log::warn!("Time order has not been enabled, see `Input::with_time_order`");
+            enable_time_order();
+        }
+        let mut stamped = Vec::new();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            slot.drain(|_, envelope| stamped.push((envelope.stamp, index, envelope.data)));
+        }
+        stamped.sort_by_key(|(stamp, _, _)| *stamp);
+        stamped.into_iter().map(|(_, index, data)| (index, data)).collect()
+    }
+
+    /// Indices of the slots without connections.
+    pub fn unconnected_slots(&self) -> Vec<usize> {
+        (0..self.slots.len()).filter(|i| self.slots[*i].sources.is_empty()).collect()
+    }
+}
+
+/// Output ports of the same type that a node creates and removes while running, e.g. one per
+/// subscriber of a multiplexer.
+///
+/// Ports are referenced by name. Forward `Node::output_port` to `Self::port` to make them
+/// available for connecting, also at runtime with `Infrastructure::connect`.
+pub struct DynamicOutputs<T: Clone> {
+    ports: Vec<(String, Output<T>)>,
+}
+
+impl<T: Clone> Default for DynamicOutputs<T> {
+    fn default() -> Self {
+        Self { ports: Vec::new() }
+    }
+}
+
+impl<T: Clone> DynamicOutputs<T> {
+    /// Add an unconnected port called `name`, replacing an existing one of the same name.
+    pub fn add(&mut self, name: impl Into<String>) -> &mut Output<T> {
+        let name = name.into();
+        self.remove(&name);
+        self.ports.push((name, Output::default()));
+        let (_, port) = self.ports.last_mut().expect("port has just been added");
+        port
+    }
+
+    /// Remove the port called `name`, closing its connections.
+    pub fn remove(&mut self, name: &str) -> Option<Output<T>> {
+        let idx = self.ports.iter().position(|(n, _)| n == name)?;
+        Some(self.ports.remove(idx).1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Output<T>> {
+        self.ports.iter_mut().find(|(n, _)| n == name).map(|(_, p)| p)
+    }
+
+    /// Names of all ports, in order of creation.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.ports.iter().map(|(n, _)| n)
+    }
+
+    /// Write data to the port called `name`, returns `false` if there is no such port.
+    pub fn fire(&mut self, name: &str, t: T) -> bool {
+        match self.get_mut(name) {
+            Some(port) => {
+                port.fire(t);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write data to all ports.
+    pub fn fire_all(&mut self, t: T) {
+        for (_, port) in &mut self.ports {
+            port.fire(t.clone());
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> DynamicOutputs<T> {
+    /// The port called `name` for `Node::output_port`.
+    pub fn port(&mut self, name: &str) -> Option<&mut dyn AnyOutput> {
+        self.get_mut(name).map(|p| p as &mut dyn AnyOutput)
+    }
+}
+
+/// Type-erased input port, used to connect ports by name.
+///
+/// Implemented by all `Input` and `FairInput` ports, see `Node::input_port`.
+pub trait AnyInput {
+    /// Name of the data type received by this port.
+    fn type_name(&self) -> &'static str;
+
+    /// Type id of the data type received by this port.
+    fn item_type_id(&self) -> TypeId;
+
+    /// Add the receiving end of a connection created by `AnyOutput::link`.
+    ///
+    /// Fails with `FlexcoreError::PortTypeMismatch` naming both types if the link carries
+    /// another type than this port. The sending end of the link stays with its output port, see
+    /// `AnyOutput::connect` to check the types before creating a link.
+    fn attach(&mut self, link: Box<dyn Any + Send>) -> Result<ConnectionId, FlexcoreError>;
+
+    /// Number of connections of this port.
+    fn connection_count(&self) -> usize;
+
+    /// Number of items waiting in the connections of this port, see `Input::buffered_count`.
+    ///
+    /// Used by the region to decide whether it is finished, see `Region`. Per default, none are
+    /// reported.
+    fn buffered_count(&self) -> u64 {
+        0
+    }
+
+    /// Ids of the connections of this port, see `InfrastructureBuilder::graph`.
+    ///
+    /// Per default, none are reported.
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        Vec::new()
+    }
+}
+
+impl<T: Send + 'static> AnyInput for Input<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn item_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn attach(&mut self, link: Box<dyn Any + Send>) -> Result<ConnectionId, FlexcoreError> {
+        let input_type = std::any::type_name::<T>();
+        let link = link.downcast::<Link>().map_err(|_| {
+            log::error!("Cannot attach a link not created by an output port to port of type {input_type}");
+            FlexcoreError::PortTypeMismatch {
+                output_type: "unknown",
+                input_type,
             }
+        })?;
+        let output_type = link.type_name;
+        let source = link.source.downcast::<Source<T>>().map_err(|_| {
+            log::error!("Cannot attach link of type {output_type} to port of type {input_type}");
+            FlexcoreError::PortTypeMismatch {
+                output_type,
+                input_type,
+            }
+        })?;
+        let id = source.id;
+        self.sources.push(*source);
+        Ok(id)
+    }
+
+    fn connection_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    fn buffered_count(&self) -> u64 {
+        Input::buffered_count(self)
+    }
+
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        self.sources.iter().map(|s| s.id).collect()
+    }
+}
+
+/// Receiving end of a connection created by `AnyOutput::link`, with the type it carries.
+struct Link {
+    type_name: &'static str,
+    source: Box<dyn Any + Send>,
+}
+
+impl Link {
+    fn boxed<T: Send + 'static>(source: Source<T>) -> Box<dyn Any + Send> {
+        Box::new(Self {
+            type_name: std::any::type_name::<T>(),
+            source: Box::new(source),
+        })
+    }
+}
+
+/// Type-erased output port, used to connect ports by name.
+///
+/// Implemented by all `Output` ports, see `Node::output_port`.
+pub trait AnyOutput {
+    /// Name of the data type sent by this port.
+    fn type_name(&self) -> &'static str;
+
+    /// Type id of the data type sent by this port.
+    fn item_type_id(&self) -> TypeId;
+
+    /// Create a new connection and return its receiving end for `AnyInput::attach`.
+    fn link(&mut self) -> Box<dyn Any + Send>;
+
+    /// Like `link`, but the connection buffers at most `capacity` items, see
+    /// `Output::connect_bounded`.
+    fn link_bounded(&mut self, capacity: usize) -> Box<dyn Any + Send>;
+
+    /// Boxed `Receiver` of `Output::subscribe`.
+    fn observe(&mut self) -> Box<dyn Any + Send>;
+
+    /// Number of connections of this port, see `Output::connection_count`.
+    fn connection_count(&self) -> usize;
+
+    /// Ids of the connections of this port, see `InfrastructureBuilder::graph`.
+    ///
+    /// Per default, none are reported.
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        Vec::new()
+    }
+}
+
+impl dyn AnyOutput + '_ {
+    /// Connect this port to `input` if both carry the same type, e.g. for ports looked up by
+    /// name from a configuration.
+    ///
+    /// Fails with `FlexcoreError::PortTypeMismatch` naming both types otherwise, without
+    /// creating a connection.
+    ///
+    /// ```
+    /// use flexcore::ports::{AnyInput, AnyOutput};
+    /// use flexcore::{FlexcoreError, Input, Output};
+    ///
+    /// let mut output = Output::<u8>::default();
+    /// let mut input = Input::<f64>::default();
+    /// let err = (&mut output as &mut dyn AnyOutput).connect(&mut input).unwrap_err();
+    /// assert_eq!(
+    ///     err,
+    ///     FlexcoreError::PortTypeMismatch {
+    ///         output_type: "u8",
+    ///         input_type: "f64",
+    ///     }
+    /// );
+    /// assert_eq!(output.connection_count(), 0);
+    ///
+    /// let mut input = Input::<u8>::default();
+    /// (&mut output as &mut dyn AnyOutput).connect(&mut input).unwrap();
+    /// output.fire(7);
+    /// assert_eq!(input.fetch(), vec![7]);
+    /// ```
+    pub fn connect(&mut self, input: &mut dyn AnyInput) -> Result<ConnectionId, FlexcoreError> {
+        if self.item_type_id() != input.item_type_id() {
+            log::error!(
+                "Cannot connect port of type {} to port of type {}",
+                self.type_name(),
+                input.type_name()
+            );
+            return Err(FlexcoreError::PortTypeMismatch {
+                output_type: self.type_name(),
+                input_type: input.type_name(),
+            });
+        }
+        input.attach(self.link())
+    }
+}
+
+impl<T: Clone + Send + 'static> AnyOutput for Output<T> {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn item_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn link(&mut self) -> Box<dyn Any + Send> {
+        let (sink, source) = channel::<T>();
+        self.sinks.push(sink);
+        Link::boxed(source)
+    }
+
+    fn link_bounded(&mut self, capacity: usize) -> Box<dyn Any + Send> {
+        let (sink, source) = bounded_channel::<T>(capacity.max(1));
+        self.sinks.push(sink);
+        Link::boxed(source)
+    }
+
+    fn observe(&mut self) -> Box<dyn Any + Send> {
+        Box::new(self.subscribe())
+    }
+
+    fn connection_count(&self) -> usize {
+        self.sinks.len()
+    }
+
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        self.sinks.iter().map(|s| s.id).collect()
+    }
+}
+
+impl<T: 'static, U: Clone + Send + 'static> AnyOutput for MappedOutput<T, U> {
+    fn type_name(&self) -> &'static str {
+        self.output.type_name()
+    }
+
+    fn item_type_id(&self) -> TypeId {
+        self.output.item_type_id()
+    }
+
+    fn link(&mut self) -> Box<dyn Any + Send> {
+        self.output.link()
+    }
+
+    fn link_bounded(&mut self, capacity: usize) -> Box<dyn Any + Send> {
+        self.output.link_bounded(capacity)
+    }
+
+    fn observe(&mut self) -> Box<dyn Any + Send> {
+        self.output.observe()
+    }
+
+    fn connection_count(&self) -> usize {
+        self.connection_ids().len()
+    }
+
+    fn connection_ids(&self) -> Vec<ConnectionId> {
+        let mut ids = self.output.connection_ids();
+        if let Some(unmapped) = &self.unmapped {
+            ids.extend(unmapped.connection_ids());
         }
+        ids
     }
 }
@@ -0,0 +1,10 @@
+//! Commonly used traits and types.
+//!
+//! Import them with `use flexcore::prelude::*;`.
+pub use crate::nodes::{DelayLine, Tee, Window, WindowSize};
+// Brings the `ports!` macro into scope.
+pub use crate::ports;
+// Brings the `fire_group!` macro into scope.
+pub use crate::fire_group;
+pub use crate::region::{NodeGroup, RegionBuilder};
+pub use crate::{FireBatchGuard, FlexcoreError, Infrastructure, InfrastructureBuilder, Input, Node, Output, Reconfigurable};
@@ -0,0 +1,221 @@
+//! Pull-based nodes, computing only when a consumer asks for a value.
+//!
+//! Push nodes (see `Node`) are executed by their region loop every tick and fire data
+//! downstream. A `PullNode` instead computes its value in `PullNode::pull`, which runs only when
+//! a downstream consumer calls `PullInput::pull` on a connected port. A pull node may pull its
+//! own `PullInput` ports while computing, so demand propagates upstream through the graph and
+//! parts of it that nobody asks for never run. This suits sparse, on-demand computations.
+//!
+//! # Execution
+//!
+//! A `PullRegion` owns pull nodes but has no thread of its own: a pull is executed synchronously
+//! in the thread of the consumer, including all pulls it causes upstream. Each pull node is
+//! guarded by a mutex, so consumers in several threads may share it and are served one at a
+//! time. The graph of pull connections must be acyclic, a node pulling itself, directly or
+//! through others, deadlocks.
+//!
+//! # Interoperation with push nodes
+//!
+//! - Pull from push: a push node holds a `PullInput` and pulls it, e.g. in `Node::tick`. The
+//!   demanded computation runs in the region thread of the push node, counting towards its
+//!   tick.
+//! - Push into pull: a pull node holds a regular `Input` connected to push outputs and reads it
+//!   when pulled. `LatestValue` does this for the common case of providing the most recent
+//!   pushed value on demand.
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use crate::{FlexcoreError, Input};
+
+/// Node computing a value on demand, see the module documentation.
+pub trait PullNode: Send + 'static {
+    /// Type of the values this node provides
+    type Output: Send + 'static;
+
+    fn name(&self) -> &String;
+
+    /// Compute the next value, `None` if there is none available.
+    ///
+    /// Called from the thread of the consumer asking for the value.
+    fn pull(&mut self) -> Option<Self::Output>;
+}
+
+/// Type-erased pull node providing values of type `T`.
+trait PullSource<T>: Send {
+    fn pull(&mut self) -> Option<T>;
+}
+
+impl<N: PullNode> PullSource<N::Output> for N {
+    fn pull(&mut self) -> Option<N::Output> {
+        PullNode::pull(self)
+    }
+}
+
+/// Port providing the values of a pull node, see `PullRegion::with_node`.
+///
+/// Cheap to clone, all clones refer to the same node.
+pub struct PullOutput<T> {
+    node: Arc<Mutex<dyn PullSource<T>>>,
+}
+
+impl<T> Clone for PullOutput<T> {
+    fn clone(&self) -> Self {
+        Self { node: self.node.clone() }
+    }
+}
+
+impl<T: 'static> PullOutput<T> {
+    /// Let `input` pull from this port.
+    ///
+    /// An input port pulls from a single output port, a previous connection is replaced.
+    pub fn connect(&self, input: &mut PullInput<T>) {
+        input.upstream = Some(self.clone());
+    }
+
+    /// Ask the node for its next value, see `PullNode::pull`.
+    pub fn pull(&self) -> Option<T> {
+        self.node.lock().unwrap_or_else(|e| e.into_inner()).pull()
+    }
+}
+
+/// Input port demanding values from a connected `PullOutput`.
+pub struct PullInput<T> {
+    upstream: Option<PullOutput<T>>,
+}
+
+impl<T> Default for PullInput<T> {
+    fn default() -> Self {
+        Self { upstream: None }
+    }
+}
+
+impl<T: 'static> PullInput<T> {
+    /// Ask the connected node for its next value.
+    ///
+    /// Blocks while the node computes, including everything it pulls in turn. `None` if the
+    /// port is not connected or the node has no value available.
+    pub fn pull(&mut self) -> Option<T> {
+        self.upstream.as_ref()?.pull()
+    }
+
+    /// Whether the port has been connected, see `PullOutput::connect`.
+    pub fn is_connected(&self) -> bool {
+        self.upstream.is_some()
+    }
+}
+
+/// Registered pull node of a `PullRegion`.
+struct PullEntry {
+    name: String,
+    /// See `std::any::type_name`
+    type_name: &'static str,
+    /// `PullOutput` of the node
+    output: Box<dyn Any + Send>,
+}
+
+/// Owner of pull nodes, see the module documentation.
+///
+/// Unlike `Region`, it does not run a loop: its nodes are executed by the consumers pulling
+/// them.
+pub struct PullRegion {
+    name: String,
+    nodes: Vec<PullEntry>,
+}
+
+impl PullRegion {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Add `node` to this region and return its output port.
+    ///
+    /// Fails with `FlexcoreError::DuplicateNode` if the region already has a node of the same
+    /// name.
+    pub fn with_node<N: PullNode>(&mut self, node: N) -> Result<PullOutput<N::Output>, FlexcoreError> {
+        let name = node.name().clone();
+        if self.nodes.iter().any(|n| n.name == name) {
+            log::error!("Pull region {} already has a node {name}", self.name);
+            return Err(FlexcoreError::DuplicateNode);
+        }
+        let output = PullOutput {
+            node: Arc::new(Mutex::new(node)),
+        };
+        self.nodes.push(PullEntry {
+            name,
+            type_name: std::any::type_name::<N::Output>(),
+            output: Box::new(output.clone()),
+        });
+        Ok(output)
+    }
+
+    /// Output port of node `node`, e.g. to connect nodes by name.
+    pub fn output<T: 'static>(&self, node: &str) -> Result<PullOutput<T>, FlexcoreError> {
+        let Some(entry) = self.nodes.iter().find(|n| n.name == node) else {
+            log::error!("Pull region {} has no node {node}", self.name);
+            return Err(FlexcoreError::UnknownNode);
+        };
+        entry.output.downcast_ref::<PullOutput<T>>().cloned().ok_or_else(|| {
+            log::error!(
+                "Pull node {node} provides {}, not {}",
+                entry.type_name,
+                std::any::type_name::<T>()
+            );
+            FlexcoreError::PortTypeMismatch {
+                output_type: entry.type_name,
+                input_type: std::any::type_name::<T>(),
+            }
+        })
+    }
+
+    /// Names of all nodes, in order of addition.
+    pub fn node_names(&self) -> Vec<String> {
+        self.nodes.iter().map(|n| n.name.clone()).collect()
+    }
+}
+
+/// Pull node providing the most recent value pushed to its input.
+///
+/// Bridges push outputs into a pull graph: connect an `Output` to `Self::input_mut`. Pulls
+/// return `None` until the first value has been received.
+pub struct LatestValue<T> {
+    name: String,
+    input: Input<T>,
+    latest: Option<T>,
+}
+
+impl<T: Clone> LatestValue<T> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            input: Input::default(),
+            latest: None,
+        }
+    }
+
+    /// The input port to connect the pushed stream to.
+    pub fn input_mut(&mut self) -> &mut Input<T> {
+        &mut self.input
+    }
+}
+
+impl<T: Clone + Send + 'static> PullNode for LatestValue<T> {
+    type Output = T;
+
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn pull(&mut self) -> Option<T> {
+        if let Some(latest) = self.input.fetch().pop() {
+            self.latest = Some(latest);
+        }
+        self.latest.clone()
+    }
+}
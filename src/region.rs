@@ -1,16 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
+
+use crate::metrics::{NodeCounters, RegionCounters};
+use crate::{group, node, panics, ports};
+use crate::shutdown::Shutdown;
 use crate::{FlexcoreError, InfrastructureBuilder, Node};
 
 pub struct RegionBuilder {
     pub(crate) name: String,
     pub(crate) tick: std::time::Duration,
-    pub(crate) nodes: Vec<Box<dyn Node>>,
+    pub(crate) nodes: Vec<NodeEntry>,
+    /// Number of node groups added so far
+    pub(crate) groups: usize,
+    pub(crate) idle_backoff: Option<IdleBackoff>,
+    pub(crate) strict_periodicity: bool,
+    pub(crate) shutdown_phase: u32,
+    pub(crate) budget: Option<Budget>,
+    pub(crate) node_watchdog: Option<std::time::Duration>,
+    pub(crate) executor: Option<String>,
+    pub(crate) priority: i32,
+    pub(crate) cpu_affinity: Vec<usize>,
+    pub(crate) restart_policy: RestartPolicy,
+    pub(crate) inbound_filter: Option<ports::InboundFilter>,
+    pub(crate) labels: BTreeMap<String, String>,
+    pub(crate) trigger: Option<Arc<RegionTrigger>>,
     pub(crate) infra: InfrastructureBuilder
 }
 
 impl RegionBuilder {
     /// Add a node to this region
-    pub fn with_node<T: Node + 'static>(mut self, node: T) -> Self {
-        self.nodes.push(Box::new(node));
+    pub fn with_node<T: Node + 'static>(self, node: T) -> Self {
+        self.with_boxed_node(Box::new(node))
+    }
+
+    /// Add a node that has already been boxed, e.g. by a node factory.
+    pub fn with_boxed_node(self, node: Box<dyn Node>) -> Self {
+        self.with_prioritized_node(node, 0)
+    }
+
+    /// Add a node that is executed before all nodes of lower priority, see `Region` for the
+    /// execution order. Nodes added with `Self::with_node` have priority 0.
+    pub fn with_prioritized_node(mut self, node: Box<dyn Node>, priority: i32) -> Self {
+        let seq = self.nodes.len() as u64;
+        self.nodes.push(NodeEntry::new(node, None, priority, seq));
+        self
+    }
+
+    /// Add all `nodes` in order, e.g. generated ones like a worker per CPU.
+    ///
+    /// Fails with `FlexcoreError::DuplicateNode` at the first node whose name is already used
+    /// by a node of this region, including the ones added before by this call, or of a region
+    /// built before.
+    pub fn with_nodes(self, nodes: impl IntoIterator<Item = Box<dyn Node>>) -> Result<Self, FlexcoreError> {
+        self.with_prioritized_nodes(nodes.into_iter().map(|node| (node, 0)))
+    }
+
+    /// Like `Self::with_nodes`, with the priority of each node, see `Self::with_prioritized_node`.
+    pub fn with_prioritized_nodes(
+        mut self,
+        nodes: impl IntoIterator<Item = (Box<dyn Node>, i32)>,
+    ) -> Result<Self, FlexcoreError> {
+        for (node, priority) in nodes {
+            if self.has_node(node.name()) {
+                log::error!("Cannot add node {} to region {}, the name is already used", node.name(), self.name);
+                return Err(FlexcoreError::DuplicateNode);
+            }
+            self = self.with_prioritized_node(node, priority);
+        }
+        Ok(self)
+    }
+
+    /// Whether a node of this region or of a region built before is called `name`.
+    fn has_node(&self, name: &str) -> bool {
+        self.nodes.iter().any(|e| e.node.name() == name) || self.infra.regions.iter().any(|r| r.has_node(name))
+    }
+
+    /// Add a group of nodes that are executed as a unit.
+    ///
+    /// See `NodeGroup` for the guarantees.
+    pub fn with_group(mut self, group: NodeGroup) -> Self {
+        let id = self.groups;
+        self.groups += 1;
+        for node in group.nodes {
+            let seq = self.nodes.len() as u64;
+            self.nodes.push(NodeEntry::new(node, Some(id), group.priority, seq));
+        }
+        self
+    }
+
+    /// Poll less frequently while the region receives no input.
+    ///
+    /// After the first loop iteration in which no node received any item on its `Input` ports,
+    /// the loop period is `min`. It is multiplied by `factor` with each further iteration without
+    /// input, up to `max`. As soon as any item is received, the loop runs with its regular tick
+    /// again. The loop period is never shorter than the tick.
+    ///
+    /// `factor` must be at least 1 and `min` must not exceed `max`.
+    pub fn with_idle_backoff(mut self, min: std::time::Duration, max: std::time::Duration, factor: f64) -> Self {
+        self.idle_backoff = Some(IdleBackoff { min, max, factor });
+        self
+    }
+
+    /// Wake up with every tick, even if the region has nothing to do.
+    ///
+    /// Per default, a region without explicit idle backoff (see `Self::with_idle_backoff`) backs
+    /// off automatically: after loop iterations in which no node received any item and no node
+    /// fired any output, the loop period doubles with each such iteration, up to 10 ms or the
+    /// tick, whichever is longer. Any received or fired item restores the regular tick. This
+    /// saves cycles of regions with nothing to do at high tick rates, at the cost of up to 10 ms
+    /// latency for the first item after a quiet phase.
+    pub fn with_strict_periodicity(mut self) -> Self {
+        self.strict_periodicity = true;
+        self
+    }
+
+    /// Set the phase in which this region stops when the infrastructure shuts down.
+    ///
+    /// Regions stop in ascending order of their phases: regions of phase 1 keep running until
+    /// all regions of phase 0 have stopped, and so on. Regions of the same phase stop together.
+    /// Before stopping, a region of a phase greater than 0 runs its nodes one final time to
+    /// process data sent by the regions that stopped before it.
+    ///
+    /// E.g. put sensor regions in phase 0 and processing regions in phase 1, so that
+    /// processing drains all sensor data instead of stopping in the middle of the stream.
+    ///
+    /// Default is phase 0.
+    pub fn with_shutdown_phase(mut self, phase: u32) -> Self {
+        self.shutdown_phase = phase;
+        self
+    }
+
+    /// Limit the processing time of a single loop iteration to `budget`.
+    ///
+    /// Nodes cannot be interrupted, so the limit is checked after each node or node group. Once
+    /// the budget is exhausted, `on_overrun` is called with the region name and the remaining
+    /// nodes of this iteration only process their inputs, their `Node::tick` is skipped. Nodes
+    /// later in the execution order (see `Region`) are thereby treated as lower priority. A node that exhausts the budget on
+    /// every iteration on its own prevents the `tick` of all nodes after it.
+    pub fn with_budget(mut self, budget: std::time::Duration, on_overrun: Box<dyn FnMut(&str) + Send>) -> Self {
+        self.budget = Some(Budget {
+            limit: budget,
+            on_overrun,
+        });
+        self
+    }
+
+    /// Run this region on the thread called `executor`, shared with all other regions assigned
+    /// to the same executor.
+    ///
+    /// Saves threads for many light regions. Regions of an executor are scheduled cooperatively:
+    /// in each scheduler pass, every region whose next iteration is due gets at most one loop
+    /// iteration, no matter how long the iterations of the other regions take. Regions run in
+    /// order of their priority (see `Self::with_priority`), the region that goes first among
+    /// regions of equal priority rotates with each pass. A region that takes long delays the
+    /// others, so nodes doing heavy work should split it across ticks, see `node::should_yield`.
+    ///
+    /// Per default, each region has a thread of its own.
+    pub fn with_executor(mut self, executor: impl Into<String>) -> Self {
+        self.executor = Some(executor.into());
+        self
+    }
+
+    /// Scheduling priority of this region on a shared executor, higher is more important.
+    ///
+    /// Within each scheduler pass, regions of higher priority run first. A region only runs if no
+    /// region of higher priority is due again by then, so busy high-priority regions delay
+    /// lower-priority ones indefinitely. Scheduling is cooperative: a running iteration is never
+    /// interrupted, so a long iteration of a low-priority region still delays all others.
+    ///
+    /// Has no effect on regions with a thread of their own. Defaults to 0.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// CPUs the thread of this region should run on, see `ThreadConfig::cpu_affinity`.
+    ///
+    /// Per default, the thread may run on any CPU.
+    pub fn with_cpu_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpu_affinity = cpus.into_iter().collect();
+        self
+    }
+
+    /// What happens if a node of this region panics, see `RestartPolicy`.
+    ///
+    /// Per default, the region stops.
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Watch for nodes that are stuck in one of their methods.
+    ///
+    /// A watchdog thread checks whether a node of this region has been running a single call of
+    /// `Node::tick` or `Node::process_input` for longer than `threshold`. If so, it logs a
+    /// warning naming the node and counts the incident in `RegionMetrics::stalls`. The node
+    /// cannot be interrupted.
+    pub fn with_node_watchdog(mut self, threshold: std::time::Duration) -> Self {
+        self.node_watchdog = Some(threshold);
+        self
+    }
+
+    /// Discard all data fetched by the nodes of this region for which `filter` returns `false`.
+    ///
+    /// Validates or sanitizes data at the region boundary without touching the nodes. The filter
+    /// runs on the region thread whenever a node fetches an item during a loop iteration, after
+    /// the filter of the port, if any (see `Input::with_filter`). Rejected items are handled like
+    /// those of a port filter.
+    ///
+    /// Ports are generic over their data type, so the filter only sees the data as `&dyn Any`
+    /// and has to downcast it to the types it knows, passing all others:
+    ///
+    /// ```ignore
+    /// .with_inbound_filter(Box::new(|data| {
+    ///     data.downcast_ref::<SensorData>().is_none_or(|d| d.data.iter().all(|v| v.is_finite()))
+    /// }))
+    /// ```
+    ///
+    /// Prefer `Input::with_filter` if the check belongs to a single port.
+    pub fn with_inbound_filter(mut self, filter: ports::InboundFilter) -> Self {
+        self.inbound_filter = Some(filter);
+        self
+    }
+
+    /// Attach `labels` to the region, e.g. `pipeline` or `tenant`, replacing labels given before.
+    ///
+    /// Labels are part of the metrics of the region (see `RegionMetrics::labels`) and exported as
+    /// additional Prometheus labels of the region and its nodes, to aggregate by them when
+    /// several pipelines run in one process. They are fixed once the region is built. Label
+    /// names must be valid Prometheus label names other than `region` and `node`, otherwise
+    /// `Self::build` fails with `FlexcoreError::InvalidLabel`.
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels.into_iter().collect();
+        self
+    }
+
+    /// Choose when the loop of this region runs, see `LoopMode`.
+    ///
+    /// Per default, the loop runs periodically with the tick of the region.
+    pub fn with_loop_mode(mut self, mode: LoopMode) -> Self {
+        self.trigger = match mode {
+            LoopMode::Periodic => None,
+            LoopMode::Triggered(trigger) => Some(Arc::new(RegionTrigger::new(trigger))),
+        };
         self
     }
 
@@ -19,22 +254,483 @@ impl RegionBuilder {
             log::error!("Region {} has no nodes assigned. Please assign at least one node using `Self::with_node`", self.name);
             return Err(FlexcoreError::NoNodes)
         }
-        let region = Region {
+        if let Some(backoff) = &self.idle_backoff {
+            if backoff.factor.is_nan() || backoff.factor < 1.0 || backoff.min > backoff.max {
+                log::error!("Idle backoff of region {} is invalid: {backoff:?}", self.name);
+                return Err(FlexcoreError::InvalidIdleBackoff)
+            }
+        }
+        if let Some(label) = self.labels.keys().find(|l| !is_valid_label(l)) {
+            log::error!("Label {label:?} of region {} is not a valid label name", self.name);
+            return Err(FlexcoreError::InvalidLabel)
+        }
+        let mut nodes = self.nodes;
+        nodes.sort_by(NodeEntry::execution_order);
+        let node_counters = nodes.iter().map(|n| n.counters.clone()).collect();
+        let next_seq = nodes.len() as u64;
+        let mut region = Region {
+            counters: Arc::new(RegionCounters::new(
+                self.name.clone(),
+                self.labels,
+                node_counters,
+                self.trigger.clone(),
+                self.restart_policy,
+            )),
             name: self.name,
-            tick: self.tick,
-            nodes: self.nodes
+            tick: Arc::new(AtomicU64::new(duration_to_nanos(self.tick))),
+            nodes,
+            idle_backoff: self.idle_backoff,
+            strict_periodicity: self.strict_periodicity,
+            quiet_iterations: 0,
+            shutdown_phase: self.shutdown_phase,
+            startup_phase: 0,
+            budget: self.budget,
+            executor: self.executor,
+            priority: self.priority,
+            cpu_affinity: self.cpu_affinity,
+            inbound_filter: self.inbound_filter,
+            idle_iterations: 0,
+            last_tick: None,
+            first_tick: None,
+            scheduled_period: None,
+            next_seq,
+            epoch: None,
+            trigger: self.trigger,
+            watchdog: self.node_watchdog.map(|threshold| {
+                Arc::new(Watchdog {
+                    threshold,
+                    current: Mutex::new(None),
+                })
+            }),
         };
+        self.infra.graph.add_region(&mut region);
         self.infra.regions.push(region);
         Ok(self.infra)
     }
 }
 
+/// What happens to a region whose loop panics, see `RegionBuilder::with_restart_policy`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Stop the region and all regions sharing its thread, see `ShutdownReason::RegionPanic`
+    #[default]
+    Never,
+    /// Keep the region and restart it with exponential backoff
+    ///
+    /// The panic is caught, so regions sharing the thread keep running. The supervisor (see
+    /// `Infrastructure::enable_supervisor`) restarts the region, so without it the region
+    /// waits forever. The nodes are not recreated: `Node::on_start` is called again on all
+    /// of them, which must cope with whatever state the panic left behind. Restarts are
+    /// logged and counted in `metrics::RegionMetrics::restarts`.
+    Always,
+}
+
+/// When the loop of a region runs, see `RegionBuilder::with_loop_mode`.
+#[derive(Debug, Clone, Default)]
+pub enum LoopMode {
+    /// Run with the tick of the region, see `InfrastructureBuilder::with_region`
+    #[default]
+    Periodic,
+    /// Run one iteration for each `Trigger::fire`, e.g. on a hardware interrupt or a signal of
+    /// another region. The tick of the region, idle backoff and aligned ticks have no effect.
+    /// Calls on the `Infrastructure` handle like `Infrastructure::with_node` do not wait for
+    /// the trigger. The supervisor (see `Infrastructure::enable_supervisor`) only reports the
+    /// region as stalled if it does not run for a fire within the threshold, not while it
+    /// waits for one.
+    Triggered(Trigger),
+}
+
+/// Handle making a triggered region run, see `LoopMode::Triggered`.
+///
+/// Clones share their state, so a trigger can be handed to other code or to nodes of other
+/// regions. Each call of `Self::fire` causes exactly one loop iteration of each region driven
+/// by the trigger: iterations run as soon as the region is idle, fires that arrive during an
+/// iteration are not merged but cause further iterations. Fires before the region has been
+/// built are ignored, fires before it has started count.
+#[derive(Debug, Clone, Default)]
+pub struct Trigger(Arc<TriggerState>);
+
+#[derive(Debug, Default)]
+struct TriggerState {
+    /// Number of `Trigger::fire` calls
+    fired: AtomicU64,
+    /// Shutdown states of the infrastructures whose threads wait for the trigger
+    waiting: Mutex<Vec<Weak<Shutdown>>>,
+}
+
+impl Trigger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the regions driven by this trigger once more.
+    ///
+    /// Non-blocking, e.g. to be called from an interrupt handler thread.
+    pub fn fire(&self) {
+        self.0.fired.fetch_add(1, Ordering::SeqCst);
+        let waiting = self.0.waiting.lock().unwrap_or_else(|e| e.into_inner());
+        for shutdown in waiting.iter().filter_map(Weak::upgrade) {
+            shutdown.notify_change();
+        }
+    }
+
+    /// Number of calls of `Self::fire` so far.
+    pub fn fire_count(&self) -> u64 {
+        self.0.fired.load(Ordering::SeqCst)
+    }
+}
+
+/// Trigger of a region together with the fires it has handled, shared with its counters.
+#[derive(Debug)]
+pub(crate) struct RegionTrigger {
+    trigger: Trigger,
+    handled: AtomicU64,
+}
+
+impl RegionTrigger {
+    fn new(trigger: Trigger) -> Self {
+        Self {
+            handled: AtomicU64::new(trigger.fire_count()),
+            trigger,
+        }
+    }
+
+    /// Whether the trigger has been fired more often than the region ran.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.trigger.fire_count() > self.handled.load(Ordering::SeqCst)
+    }
+
+    /// Count an iteration of the region.
+    fn handle(&self) {
+        let fired = self.trigger.fire_count();
+        let _ = self
+            .handled
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |handled| Some((handled + 1).min(fired)));
+    }
+}
+
+/// Whether `label` can be used as Prometheus label name and does not collide with the built-in ones.
+fn is_valid_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !label.starts_with("__")
+        && label != "region"
+        && label != "node"
+}
+
+/// Convert a tick given in seconds, e.g. from a configuration file.
+pub(crate) fn tick_from_secs(secs: f64) -> Result<std::time::Duration, FlexcoreError> {
+    if !secs.is_finite() || secs <= 0.0 {
+        log::error!("Tick of {secs} s is not a finite positive number");
+        return Err(FlexcoreError::InvalidTick);
+    }
+    let tick = std::time::Duration::try_from_secs_f64(secs).map_err(|e| {
+        log::error!("Tick of {secs} s is not usable: {e}");
+        FlexcoreError::InvalidTick
+    })?;
+    if tick.is_zero() {
+        log::error!("Tick of {secs} s is shorter than a nanosecond");
+        return Err(FlexcoreError::InvalidTick);
+    }
+    Ok(tick)
+}
+
+/// Whether `tick` is a non-zero multiple of `base`, see `InfrastructureBuilder::with_aligned_ticks`.
+pub(crate) fn is_multiple_of(tick: std::time::Duration, base: std::time::Duration) -> bool {
+    let base = duration_to_nanos(base);
+    base > 0 && !tick.is_zero() && duration_to_nanos(tick).is_multiple_of(base)
+}
+
+/// Convert a tick duration to the representation shared with running regions.
+///
+/// Saturates at roughly 584 years.
+pub(crate) fn duration_to_nanos(d: std::time::Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Loop period scaling while a region receives no input.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct IdleBackoff {
+    min: std::time::Duration,
+    max: std::time::Duration,
+    factor: f64,
+}
+
+impl IdleBackoff {
+    /// Loop period after `idle_iterations` consecutive iterations without input.
+    pub(crate) fn period(&self, idle_iterations: u32) -> std::time::Duration {
+        let exponent = i32::try_from(idle_iterations.saturating_sub(1)).unwrap_or(i32::MAX);
+        let scaled = self.min.as_secs_f64() * self.factor.powi(exponent);
+        if scaled.is_finite() && scaled < self.max.as_secs_f64() {
+            std::time::Duration::from_secs_f64(scaled)
+        } else {
+            self.max
+        }
+    }
+}
+
+/// Longest loop period of the automatic idle backoff, see `RegionBuilder::with_strict_periodicity`.
+const AUTO_IDLE_MAX_PERIOD: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Processing time limit of a loop iteration.
+pub(crate) struct Budget {
+    limit: std::time::Duration,
+    on_overrun: Box<dyn FnMut(&str) + Send>,
+}
+
+/// Node call currently executed by a region, observed by a watchdog thread.
+pub(crate) struct Watchdog {
+    threshold: std::time::Duration,
+    /// Node being executed and start of the call
+    current: Mutex<Option<(Arc<NodeCounters>, Instant)>>,
+}
+
+impl Watchdog {
+    fn set_current(&self, current: Option<(Arc<NodeCounters>, Instant)>) {
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = current;
+    }
+
+    /// Check the region for stuck nodes until the watchdog is dropped together with the region.
+    fn watch(watchdog: Weak<Watchdog>, region: String, counters: Arc<RegionCounters>) {
+        let poll_interval = match watchdog.upgrade() {
+            Some(w) => (w.threshold / 4).max(std::time::Duration::from_millis(1)),
+            None => return,
+        };
+        // Start of the last call that has been reported, to report each stuck call only once.
+        let mut reported: Option<Instant> = None;
+        loop {
+            std::thread::sleep(poll_interval);
+            let Some(w) = watchdog.upgrade() else {
+                return;
+            };
+            let current = w.current.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((node, start)) = &*current {
+                let running = start.elapsed();
+                if running > w.threshold && reported != Some(*start) {
+                    reported = Some(*start);
+                    counters.stalls.fetch_add(1, Ordering::Relaxed);
+                    log::warn!(
+                        "Node {} in region {region} has been running for {} s",
+                        node.name,
+                        running.as_secs_f64()
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Closure executed by a region thread between two iterations of the region loop.
+pub(crate) type RegionCommand = Box<dyn FnOnce(&mut Region) + Send>;
+
+/// Access to a running region from outside of its thread.
+pub(crate) struct RegionHandle {
+    pub(crate) name: String,
+    /// Work tick duration in nanoseconds, read by the region thread on each iteration
+    pub(crate) tick: Arc<AtomicU64>,
+    /// Commands to execute in the region thread
+    pub(crate) commands: Sender<RegionCommand>,
+    pub(crate) counters: Arc<RegionCounters>,
+}
+
+/// Nodes that are executed as a unit within a region.
+///
+/// In each loop iteration, `Node::tick` of all nodes of a group is called back-to-back in the
+/// order the nodes have been added, followed by `Node::process_input` and then `Node::flush` of
+/// all of them. No other node of the region is executed in between, and no command (e.g.
+/// `Infrastructure::with_node`) is executed in between. If the region budget is exhausted (see `RegionBuilder::with_budget`),
+/// either all or none of the ticks of a group are skipped.
+///
+/// All nodes of a group share the tick of their region.
+#[derive(Default)]
+pub struct NodeGroup {
+    nodes: Vec<Box<dyn Node>>,
+    priority: i32,
+}
+
+impl NodeGroup {
+    /// Create an empty group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node to this group
+    pub fn with_node<T: Node + 'static>(mut self, node: T) -> Self {
+        self.nodes.push(Box::new(node));
+        self
+    }
+
+    /// Execution priority of all nodes of the group, see `RegionBuilder::with_prioritized_node`.
+    /// Defaults to 0.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// A node in a region together with its statistics.
+pub(crate) struct NodeEntry {
+    pub(crate) node: Box<dyn Node>,
+    pub(crate) counters: Arc<NodeCounters>,
+    /// Group the node belongs to, see `NodeGroup`
+    group: Option<usize>,
+    /// Execution priority, see `RegionBuilder::with_prioritized_node`
+    priority: i32,
+    /// Position in the order the nodes have been added to the region
+    seq: u64,
+    /// Items the node may still fetch in the current iteration
+    fetch_budget: Option<usize>,
+    /// Whether the node reported not to be ready, see `Node::is_ready`
+    waiting: bool,
+}
+
+impl NodeEntry {
+    fn new(node: Box<dyn Node>, group: Option<usize>, priority: i32, seq: u64) -> Self {
+        Self {
+            counters: Arc::new(NodeCounters::new(node.name().clone())),
+            node,
+            group,
+            priority,
+            seq,
+            fetch_budget: None,
+            waiting: false,
+        }
+    }
+
+    /// Order of execution within a region, see `Region`.
+    fn execution_order(a: &Self, b: &Self) -> std::cmp::Ordering {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.seq.cmp(&b.seq))
+            .then_with(|| a.node.name().cmp(b.node.name()))
+    }
+
+    /// Whether the node is ready to tick, logging when it becomes ready.
+    fn is_ready(&mut self, region: &str) -> bool {
+        let ready = self.node.is_ready();
+        if ready && self.waiting {
+            log::info!("Node {} in region {region} is ready", self.counters.name);
+        }
+        self.waiting = !ready;
+        ready
+    }
+
+    /// Call `f` on the node, keeping track of its statistics and fetch budget.
+    fn call(&mut self, watchdog: Option<&Watchdog>, f: impl FnOnce(&mut dyn Node)) {
+        let received = ports::thread_received_count();
+        let fired = ports::thread_fired_count();
+        let gaps = ports::thread_gap_count();
+        #[cfg(feature = "contention")]
+        let channel_nanos = ports::thread_channel_nanos();
+        ports::set_fetch_budget(self.fetch_budget);
+        if let Some(watchdog) = watchdog {
+            watchdog.set_current(Some((self.counters.clone(), Instant::now())));
+        }
+        panics::set_current_node(Some(self.counters.clone()));
+        {
+            let _call = group::NodeCallGuard::enter();
+            f(self.node.as_mut());
+        }
+        panics::set_current_node(None);
+        if let Some(watchdog) = watchdog {
+            watchdog.set_current(None);
+        }
+        self.fetch_budget = ports::fetch_budget();
+        ports::set_fetch_budget(None);
+        self.counters
+            .received
+            .fetch_add(ports::thread_received_count() - received, Ordering::Relaxed);
+        self.counters
+            .fired
+            .fetch_add(ports::thread_fired_count() - fired, Ordering::Relaxed);
+        self.counters
+            .gaps
+            .fetch_add(ports::thread_gap_count() - gaps, Ordering::Relaxed);
+        #[cfg(feature = "contention")]
+        for (counter, (now, before)) in self
+            .counters
+            .channel_nanos
+            .iter()
+            .zip(ports::thread_channel_nanos().into_iter().zip(channel_nanos))
+        {
+            counter.fetch_add(now - before, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        self.counters.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A set of nodes executed by one thread with a fixed tick.
+///
+/// # Termination
+///
+/// The region loop runs until the infrastructure is dropped, or until the region is finished:
+/// after an iteration in which none of its nodes received any item on an `Input` port, the
+/// region stops if it has at least one node, `Node::is_finished` returns true for all of them
+/// and no items are waiting on their named input ports (see `Node::input_port_names` and
+/// `Input::buffered_count`). The region cannot see ports without a name, a node with such ports
+/// should only report to be finished once they are drained, e.g. with `Input::is_exhausted`.
+/// Its nodes, and with them their `Output` ports, are dropped afterwards, so downstream inputs
+/// become exhausted once they have read the remaining data.
+///
+/// # Execution order
+///
+/// Nodes are executed in descending order of their priority (see
+/// `RegionBuilder::with_prioritized_node`), nodes of equal priority in the order they have been
+/// added to the region, with the node name as final tie-breaker. The order only depends on
+/// these properties, not on the history of additions and removals at runtime: a node added
+/// with `Infrastructure::add_node` is inserted at its position, removing a node keeps the
+/// order of the others. Nodes of a group share the priority of the group and stay together.
+/// See `Self::execution_order` and `Infrastructure::node_names`.
 pub struct Region {
     name: String,
-    /// Work tick duration
-    tick: std::time::Duration,
+    /// Work tick duration in nanoseconds, shared with the running infrastructure
+    tick: Arc<AtomicU64>,
     /// Processing nodes in this region
-    nodes: Vec<Box<dyn Node>>,
+    nodes: Vec<NodeEntry>,
+    /// Statistics, shared with the running infrastructure
+    counters: Arc<RegionCounters>,
+    /// Loop period scaling while idle
+    idle_backoff: Option<IdleBackoff>,
+    /// Whether to back off automatically, see `RegionBuilder::with_strict_periodicity`
+    strict_periodicity: bool,
+    /// Consecutive iterations without received or fired data
+    quiet_iterations: u32,
+    /// Order in which regions stop
+    shutdown_phase: u32,
+    /// Order in which regions start
+    startup_phase: u32,
+    /// Processing time limit of a loop iteration
+    budget: Option<Budget>,
+    /// Shared with the watchdog thread, if any
+    watchdog: Option<Arc<Watchdog>>,
+    /// Thread shared with other regions, see `RegionBuilder::with_executor`
+    executor: Option<String>,
+    /// Scheduling priority on a shared executor
+    priority: i32,
+    /// See `RegionBuilder::with_cpu_affinity`
+    cpu_affinity: Vec<usize>,
+    /// See `RegionBuilder::with_inbound_filter`
+    inbound_filter: Option<ports::InboundFilter>,
+    /// Consecutive iterations without received data
+    idle_iterations: u32,
+    /// Timing of the previous iteration, see `node::TickContext`
+    last_tick: Option<node::TickContext>,
+    /// Start of the first iteration
+    first_tick: Option<Instant>,
+    /// Time from the start of the previous iteration to the scheduled start of the next one
+    scheduled_period: Option<std::time::Duration>,
+    /// Insertion sequence number of the next node added at runtime
+    next_seq: u64,
+    /// Start of the common schedule, see `InfrastructureBuilder::with_aligned_ticks`
+    epoch: Option<Instant>,
+    /// Set for regions running on demand, see `LoopMode::Triggered`
+    trigger: Option<Arc<RegionTrigger>>,
 }
 
 impl Region {
@@ -43,10 +739,350 @@ impl Region {
     }
 
     pub(crate) fn tick(&self) -> std::time::Duration {
-        self.tick
+        std::time::Duration::from_nanos(self.tick.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn shutdown_phase(&self) -> u32 {
+        self.shutdown_phase
+    }
+
+    pub(crate) fn startup_phase(&self) -> u32 {
+        self.startup_phase
+    }
+
+    pub(crate) fn set_startup_phase(&mut self, phase: u32) {
+        self.startup_phase = phase;
+    }
+
+    pub(crate) fn executor(&self) -> Option<&String> {
+        self.executor.as_ref()
+    }
+
+    pub(crate) fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub(crate) fn cpu_affinity(&self) -> &[usize] {
+        &self.cpu_affinity
+    }
+
+    /// Start iterations at multiples of the tick since `epoch`, see
+    /// `InfrastructureBuilder::with_aligned_ticks`.
+    pub(crate) fn set_epoch(&mut self, epoch: Instant) {
+        self.epoch = Some(epoch);
+    }
+
+    /// First tick boundary at or after `t` if the region is aligned, `t` otherwise.
+    pub(crate) fn align(&self, t: Instant) -> Instant {
+        let Some(epoch) = self.epoch else {
+            return t;
+        };
+        let tick = duration_to_nanos(self.tick()).max(1);
+        let since = duration_to_nanos(t.saturating_duration_since(epoch));
+        epoch + std::time::Duration::from_nanos(since.div_ceil(tick).saturating_mul(tick))
+    }
+
+    pub(crate) fn is_triggered(&self) -> bool {
+        self.trigger.is_some()
+    }
+
+    /// Let the trigger of this region wake the threads of `shutdown`, see `LoopMode::Triggered`.
+    pub(crate) fn attach_trigger(&self, shutdown: &Arc<Shutdown>) {
+        if let Some(trigger) = &self.trigger {
+            let mut waiting = trigger.trigger.0.waiting.lock().unwrap_or_else(|e| e.into_inner());
+            waiting.retain(|s| s.strong_count() > 0);
+            waiting.push(Arc::downgrade(shutdown));
+        }
     }
 
-    pub(crate) fn nodes_mut(&mut self) -> &mut Vec<Box<dyn Node>> {
-        &mut self.nodes
+    /// Whether the trigger of this region has been fired more often than the region ran.
+    pub(crate) fn is_trigger_pending(&self) -> bool {
+        self.trigger.as_ref().is_some_and(|t| t.is_pending())
+    }
+
+    pub(crate) fn restart_policy(&self) -> RestartPolicy {
+        self.counters.restart_policy
+    }
+
+    /// Whether the region panicked and waits to be restarted, see `RestartPolicy::Always`.
+    pub(crate) fn is_failed(&self) -> bool {
+        self.counters.failure.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    /// Clean up after a panic of an iteration and wait to be restarted.
+    pub(crate) fn fail(&mut self, message: String) {
+        log::error!("Region {} panicked, waiting for restart: {message}", self.name);
+        // Left behind by the interrupted iteration
+        panics::set_current_node(None);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_current(None);
+        }
+        ports::set_fetch_budget(None);
+        if let Some(filter) = ports::take_inbound_filter() {
+            self.inbound_filter = Some(filter);
+        }
+        node::set_yield_deadline(None);
+        node::set_tick_context(None);
+        node::set_current_region("");
+        *self.counters.failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(message);
+    }
+
+    /// Restart a failed region if the supervisor asked to, returns whether it did.
+    pub(crate) fn restart_if_requested(&mut self) -> bool {
+        if !self.counters.restart_requested.swap(false, Ordering::SeqCst) {
+            return false;
+        }
+        self.counters.restarts.fetch_add(1, Ordering::Relaxed);
+        self.idle_iterations = 0;
+        self.quiet_iterations = 0;
+        let watchdog = self.watchdog.as_deref();
+        for entry in &mut self.nodes {
+            entry.call(watchdog, |n| n.on_start());
+        }
+        *self.counters.failure.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        true
+    }
+
+    pub(crate) fn tick_handle(&self) -> Arc<AtomicU64> {
+        self.tick.clone()
+    }
+
+    pub(crate) fn counters(&self) -> &Arc<RegionCounters> {
+        &self.counters
+    }
+
+    pub(crate) fn has_node(&self, name: &str) -> bool {
+        self.nodes.iter().any(|n| n.node.name() == name)
+    }
+
+    pub(crate) fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Node>> {
+        self.nodes.iter_mut().map(|n| &mut n.node)
+    }
+
+    pub(crate) fn node_mut(&mut self, name: &str) -> Option<&mut Box<dyn Node>> {
+        self.nodes.iter_mut().map(|n| &mut n.node).find(|n| n.name() == name)
+    }
+
+    /// Remove the node called `name` from the region and return it.
+    pub(crate) fn remove_node(&mut self, name: &str) -> Option<Box<dyn Node>> {
+        let idx = self.nodes.iter().position(|n| n.node.name() == name)?;
+        let entry = self.nodes.remove(idx);
+        let mut counters = self.counters.nodes.lock().unwrap_or_else(|e| e.into_inner());
+        counters.retain(|c| !Arc::ptr_eq(c, &entry.counters));
+        Some(entry.node)
+    }
+
+    /// Add `node` to the running region at its position in the execution order and start it,
+    /// see `Node::on_start`.
+    ///
+    /// Returns the node if the region already has a node of the same name.
+    pub(crate) fn add_node(&mut self, node: Box<dyn Node>, priority: i32) -> Result<(), Box<dyn Node>> {
+        if self.nodes.iter().any(|n| n.node.name() == node.name()) {
+            return Err(node);
+        }
+        let mut entry = NodeEntry::new(node, None, priority, self.next_seq);
+        self.next_seq += 1;
+        entry.call(self.watchdog.as_deref(), |n| n.on_start());
+        let idx = self
+            .nodes
+            .partition_point(|e| NodeEntry::execution_order(e, &entry).is_lt());
+        self.nodes.insert(idx, entry);
+        let mut counters = self.counters.nodes.lock().unwrap_or_else(|e| e.into_inner());
+        *counters = self.nodes.iter().map(|e| e.counters.clone()).collect();
+        Ok(())
+    }
+
+    /// Names of the nodes in the order they are executed, see the type documentation.
+    pub fn execution_order(&self) -> Vec<String> {
+        self.nodes.iter().map(|e| e.node.name().clone()).collect()
+    }
+
+    /// Start the helper threads of the region and the nodes, call before the first `Self::step`.
+    pub(crate) fn start(&mut self) {
+        if let Some(watchdog) = &self.watchdog {
+            let watchdog = Arc::downgrade(watchdog);
+            let name = self.name.clone();
+            let counters = self.counters.clone();
+            let spawn_res = std::thread::Builder::new()
+                .name(crate::thread_name(format!("{}-watchdog", self.name)))
+                .spawn(move || Watchdog::watch(watchdog, name, counters));
+            if let Err(e) = spawn_res {
+                log::error!("Could not start watchdog thread for region {}: {e}", self.name);
+            }
+        }
+        let watchdog = self.watchdog.as_deref();
+        for entry in &mut self.nodes {
+            entry.call(watchdog, |n| n.on_start());
+        }
+    }
+
+    /// Execute the commands sent by the infrastructure handle, see `RegionCommand`.
+    pub(crate) fn run_commands(&mut self, commands: &Receiver<RegionCommand>) {
+        while let Ok(command) = commands.try_recv() {
+            command(self);
+        }
+    }
+
+    /// Single iteration of the region loop.
+    ///
+    /// Returns the time to wait before the next iteration, or `None` once the region is supposed
+    /// to stop.
+    pub(crate) fn step(&mut self, commands: &Receiver<RegionCommand>, shutdown: &Shutdown) -> Option<std::time::Duration> {
+        let start_time = Instant::now();
+        if shutdown.may_stop(self.shutdown_phase) {
+            if self.shutdown_phase > 0 && !self.is_failed() {
+                self.process();
+            }
+            return None;
+        }
+        self.run_commands(commands);
+        if let Some(trigger) = &self.trigger {
+            trigger.handle();
+        }
+        let tick = self.tick();
+        let slice = match &self.budget {
+            Some(budget) => tick.min(budget.limit),
+            None => tick,
+        };
+        node::set_yield_deadline(Some(start_time + slice));
+        let context = self.tick_context(start_time);
+        if let Some(scheduled) = self.scheduled_period.take() {
+            self.counters.record_jitter(context.dt.as_secs_f64() - scheduled.as_secs_f64());
+        }
+        node::set_tick_context(Some(context));
+        node::set_current_region(&self.name);
+        let received_before = ports::thread_received_count();
+        let fired_before = ports::thread_fired_count();
+        self.process();
+        node::set_yield_deadline(None);
+        node::set_tick_context(None);
+        node::set_current_region("");
+        if ports::thread_received_count() == received_before {
+            self.idle_iterations = self.idle_iterations.saturating_add(1);
+            if self.is_finished() {
+                log::info!("All nodes of region {} finished", self.name);
+                return None;
+            }
+        } else {
+            self.idle_iterations = 0;
+        }
+        if self.idle_iterations > 0 && ports::thread_fired_count() == fired_before {
+            self.quiet_iterations = self.quiet_iterations.saturating_add(1);
+        } else {
+            self.quiet_iterations = 0;
+        }
+        let elapsed = start_time.elapsed();
+        let period = match self.idle_backoff {
+            Some(backoff) if self.idle_iterations > 0 => tick.max(backoff.period(self.idle_iterations)),
+            None if !self.strict_periodicity && self.quiet_iterations > 0 => {
+                let backoff = IdleBackoff {
+                    min: tick,
+                    max: tick.max(AUTO_IDLE_MAX_PERIOD),
+                    factor: 2.0,
+                };
+                backoff.period(self.quiet_iterations)
+            }
+            _ => tick,
+        };
+        self.counters.iterations.fetch_add(1, Ordering::Relaxed);
+        self.scheduled_period = Some(elapsed.max(period));
+        if elapsed > tick {
+            self.counters.overruns.fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "Timing in region {} exceeded by {} s",
+                self.name,
+                elapsed.as_secs_f64()
+            );
+        }
+        if self.epoch.is_some() {
+            // Boundary the iteration was scheduled for, the start may have been late.
+            let boundary = self.align(start_time.checked_sub(tick / 2).unwrap_or(start_time));
+            let now = Instant::now();
+            let next = self.align((boundary + period).max(now));
+            Some(next - now)
+        } else if elapsed > tick {
+            Some(std::time::Duration::ZERO)
+        } else {
+            Some(period - elapsed)
+        }
+    }
+
+    /// Timing of the iteration starting at `now`.
+    fn tick_context(&mut self, now: Instant) -> node::TickContext {
+        let first_tick = *self.first_tick.get_or_insert(now);
+        let uptime = now - first_tick;
+        let context = match self.last_tick {
+            Some(last) => node::TickContext {
+                tick_index: last.tick_index + 1,
+                uptime,
+                dt: uptime - last.uptime,
+            },
+            None => node::TickContext {
+                tick_index: 0,
+                uptime,
+                dt: self.tick(),
+            },
+        };
+        self.last_tick = Some(context);
+        context
+    }
+
+    /// Whether the region has nodes, all of them are finished and their named inputs are drained.
+    fn is_finished(&mut self) -> bool {
+        !self.nodes.is_empty()
+            && self.nodes.iter_mut().all(|e| {
+                let node = &mut e.node;
+                node.is_finished()
+                    && node
+                        .input_port_names()
+                        .iter()
+                        .all(|name| node.input_port(name).is_none_or(|port| port.buffered_count() == 0))
+            })
+    }
+
+    /// Run all nodes once.
+    fn process(&mut self) {
+        let _iteration = group::IterationGuard::enter();
+        ports::set_inbound_filter(self.inbound_filter.take());
+        self.process_nodes();
+        self.inbound_filter = ports::take_inbound_filter();
+    }
+
+    fn process_nodes(&mut self) {
+        let start_time = Instant::now();
+        let mut over_budget = false;
+        let watchdog = self.watchdog.as_deref();
+        let mut remaining = self.nodes.as_mut_slice();
+        while let Some(first) = remaining.first() {
+            // A single node or a whole group
+            let len = match first.group {
+                Some(group) => remaining.iter().take_while(|e| e.group == Some(group)).count(),
+                None => 1,
+            };
+            let (unit, rest) = remaining.split_at_mut(len);
+            remaining = rest;
+            for entry in unit.iter_mut() {
+                entry.fetch_budget = entry.node.max_items_per_tick();
+            }
+            if !over_budget {
+                for entry in unit.iter_mut() {
+                    if entry.is_ready(&self.name) {
+                        entry.call(watchdog, |n| n.tick());
+                    }
+                }
+            }
+            for entry in unit.iter_mut() {
+                entry.call(watchdog, |n| n.process_input());
+            }
+            for entry in unit.iter_mut() {
+                entry.call(watchdog, |n| n.flush());
+            }
+            if let Some(budget) = &mut self.budget {
+                if !over_budget && start_time.elapsed() > budget.limit {
+                    over_budget = true;
+                    (budget.on_overrun)(&self.name);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,92 @@
+//! Output ports for data that is serializable but not `Clone`.
+//!
+//! `SerdeOutput` broadcasts by serializing each item once with `bincode` and deserializing an
+//! independent copy per additional connection. This is considerably slower than `Clone` for most
+//! types, as every copy pays for decoding and its allocations, while encoding is shared by all
+//! connections. Prefer `Output` whenever the data type implements `Clone`, e.g. via derive.
+//! With a single connection, items are moved and never serialized.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::ports::{self, bounded_channel, channel, Sink};
+use crate::{ConnectionId, Input};
+
+/// Output port for data types that implement `Serialize` and `Deserialize` but not `Clone`.
+///
+/// Consumers use a regular `Input`. See the module documentation for the performance trade-off.
+pub struct SerdeOutput<T> {
+    sinks: Vec<Sink<T>>,
+}
+
+impl<T> Default for SerdeOutput<T> {
+    fn default() -> Self {
+        Self { sinks: Vec::new() }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> SerdeOutput<T> {
+    /// Connect this output to `input`, see `Output::connect`.
+    pub fn connect(&mut self, input: &mut Input<T>) -> ConnectionId {
+        let (sink, source) = channel();
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Connect this output to `input` buffering at most `capacity` items, see
+    /// `Output::connect_bounded`.
+    pub fn connect_bounded(&mut self, input: &mut Input<T>, capacity: usize) -> ConnectionId {
+        let (sink, source) = bounded_channel(capacity.max(1));
+        let id = sink.id;
+        self.sinks.push(sink);
+        input.sources.push(source);
+        id
+    }
+
+    /// Number of open connections of this port.
+    pub fn connection_count(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Write data to this port, see `Output::fire`.
+    ///
+    /// The last connection receives `t` itself, all others a deserialized copy. If `t` cannot be
+    /// serialized or a copy cannot be deserialized, the affected connections miss the item and an
+    /// error is logged.
+    pub fn fire(&mut self, t: T) {
+        ports::count_fire();
+        let mut copies = Vec::with_capacity(self.sinks.len());
+        if self.sinks.len() > 1 {
+            match bincode::serialize(&t) {
+                Ok(bytes) => {
+                    for _ in 1..self.sinks.len() {
+                        match bincode::deserialize(&bytes) {
+                            Ok(copy) => copies.push(Some(copy)),
+                            Err(e) => {
+                                log::error!("Cannot deserialize copy of {}: {e}", std::any::type_name::<T>());
+                                copies.push(None);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Cannot serialize {}: {e}", std::any::type_name::<T>());
+                    copies.resize_with(self.sinks.len() - 1, || None);
+                }
+            }
+        }
+        copies.push(Some(t));
+        let mut copies = copies.into_iter();
+        self.sinks.retain_mut(|sink| match copies.next().flatten() {
+            Some(copy) => {
+                let open = sink.send(copy);
+                if !open {
+                    log::debug!("Removing connection {} to dropped input port", sink.id);
+                }
+                open
+            }
+            None => true,
+        });
+    }
+}
@@ -0,0 +1,216 @@
+//! Coordination of stopping the region threads.
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+/// Shutdown state shared by all region threads of an infrastructure.
+///
+/// Regions stop in ascending order of their shutdown phase: once shutdown is requested, a region
+/// stops as soon as all regions of lower phases have stopped.
+#[derive(Debug, Default)]
+pub(crate) struct Shutdown {
+    requested: AtomicBool,
+    /// Number of running regions per shutdown phase
+    running: Mutex<BTreeMap<u32, usize>>,
+    /// States of merged infrastructures, requested together with this one
+    linked: Mutex<Vec<Arc<Shutdown>>>,
+    /// Why the infrastructure stops, the first reason wins
+    reason: Mutex<Option<ShutdownReason>>,
+    /// Incremented whenever shutdown is requested or a region stops, see `Self::sleep_until`
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Shutdown {
+    /// Ask all regions to stop.
+    pub(crate) fn request(&self, reason: ShutdownReason) {
+        self.set_reason(reason.clone());
+        self.requested.store(true, Ordering::Release);
+        self.notify_change();
+        let linked = self.linked.lock().unwrap_or_else(|e| e.into_inner());
+        for other in linked.iter() {
+            other.request(reason.clone());
+        }
+    }
+
+    /// Record `reason` unless there already is one.
+    pub(crate) fn set_reason(&self, reason: ShutdownReason) {
+        let mut current = self.reason.lock().unwrap_or_else(|e| e.into_inner());
+        if current.is_none() {
+            log::info!("Infrastructure stops: {reason:?}");
+            *current = Some(reason);
+        }
+    }
+
+    pub(crate) fn reason(&self) -> Option<ShutdownReason> {
+        self.reason.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Wake all threads in `Self::sleep_until`.
+    pub(crate) fn notify_change(&self) {
+        *self.generation.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.changed.notify_all();
+    }
+
+    /// Current generation for `Self::sleep_until`, to be taken before deciding to sleep.
+    pub(crate) fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sleep until `deadline`, or until shutdown is requested or a region stops after
+    /// `generation` has been taken.
+    ///
+    /// Lets region threads react to shutdown right away instead of after their tick.
+    pub(crate) fn sleep_until(&self, deadline: Instant, generation: u64) {
+        let current = self.generation.lock().unwrap_or_else(|e| e.into_inner());
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let _ = self
+            .changed
+            .wait_timeout_while(current, timeout, |current| *current == generation)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// Whether shutdown has been requested.
+    pub(crate) fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    /// Request `other` as well whenever this is requested.
+    pub(crate) fn link(&self, other: Arc<Shutdown>) {
+        self.linked.lock().unwrap_or_else(|e| e.into_inner()).push(other);
+    }
+
+    /// Whether a region of shutdown phase `phase` shall stop now.
+    pub(crate) fn may_stop(&self, phase: u32) -> bool {
+        if !self.is_requested() {
+            return false;
+        }
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.range(..phase).all(|(_, n)| *n == 0)
+    }
+
+    /// Mark region `region` of shutdown phase `phase` as running until the returned guard is
+    /// dropped.
+    pub(crate) fn register(self: &Arc<Self>, region: String, phase: u32) -> RunningGuard {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        *running.entry(phase).or_default() += 1;
+        RunningGuard {
+            shutdown: self.clone(),
+            region,
+            phase,
+        }
+    }
+}
+
+/// Marks a region as running while alive, see `Shutdown::register`.
+pub(crate) struct RunningGuard {
+    shutdown: Arc<Shutdown>,
+    region: String,
+    phase: u32,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.shutdown.set_reason(ShutdownReason::RegionPanic {
+                region: self.region.clone(),
+            });
+        }
+        let mut running = self.shutdown.running.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(n) = running.get_mut(&self.phase) {
+            *n = n.saturating_sub(1);
+        }
+        drop(running);
+        // Regions of later phases may stop now.
+        self.shutdown.notify_change();
+    }
+}
+
+/// Why an infrastructure stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The `Infrastructure` handle has been dropped or shut down.
+    Requested,
+    /// A node asked to stop, see `ShutdownToken::request_by_node`.
+    Node {
+        /// Region of the node, if it asked while being executed by its region
+        region: Option<String>,
+        node: String,
+    },
+    /// A signal has been received, see `ShutdownToken::request`.
+    Signal(i32),
+    /// A region thread panicked. Does not stop the other regions by itself.
+    RegionPanic { region: String },
+    /// All regions finished on their own, see `Node::is_finished`.
+    Finished,
+}
+
+/// How the thread of a region exited, see `Infrastructure::shutdown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionExit {
+    /// The region stopped normally.
+    Ok,
+    /// The region thread panicked with the given message.
+    Panicked(String),
+    /// The region thread was still running at the end of the shutdown timeout, see
+    /// `InfrastructureBuilder::with_shutdown_timeout`.
+    TimedOut,
+}
+
+/// Outcome of `Infrastructure::shutdown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Why the infrastructure stopped
+    pub reason: ShutdownReason,
+    /// Exit status of each region whose thread has been started
+    pub regions: HashMap<String, RegionExit>,
+}
+
+/// Message of a panic payload as passed to `std::panic::panic_any`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".into()
+    }
+}
+
+/// Tells whether the infrastructure is shutting down, e.g. to abort blocking operations in nodes.
+///
+/// Obtained from `InfrastructureBuilder::shutdown_token` before the nodes are moved into their
+/// regions, or from `Infrastructure::shutdown_token`.
+#[derive(Debug, Clone)]
+pub struct ShutdownToken(pub(crate) Arc<Shutdown>);
+
+impl ShutdownToken {
+    /// Whether the infrastructure has been asked to stop.
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.is_requested()
+    }
+
+    /// Ask the infrastructure to stop for `reason`, e.g. from a signal handler.
+    ///
+    /// Only the first reason is kept.
+    pub fn request(&self, reason: ShutdownReason) {
+        self.0.request(reason);
+    }
+
+    /// Ask the infrastructure to stop on behalf of node `node`.
+    ///
+    /// If called from within a node method, the reason also names the region.
+    pub fn request_by_node(&self, node: &str) {
+        self.request(ShutdownReason::Node {
+            region: crate::node::current_region(),
+            node: node.to_string(),
+        });
+    }
+
+    /// Why the infrastructure stops, if known yet.
+    pub fn reason(&self) -> Option<ShutdownReason> {
+        self.0.reason()
+    }
+}
@@ -0,0 +1,294 @@
+//! Output ports that spill to disk instead of dropping data.
+//!
+//! Each connection keeps at most a configured number of items in memory. Further items are
+//! serialized with a `Codec`, `bincode` per default, into a temporary file, each one prefixed by
+//! its length as big-endian `u32`, and moved back into memory by a background thread as the
+//! consumer catches up. No data is lost under transient backpressure, at the cost of latency and
+//! disk space.
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{self, Codec, Format};
+use crate::ports::{self, channel, ConnectionHandle, Sink};
+use crate::{ConnectionId, Input};
+
+/// Polling interval of the thread moving spilled data back into memory while there is any.
+const PUMP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Longest wait of an idle pump thread before it checks whether its port still exists.
+const PUMP_IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Output port whose connections spill data to disk once `threshold` items are buffered in memory.
+///
+/// Data is delivered in the order it was fired, spilled or not. Consumers use a regular `Input`.
+///
+/// Spill files are created in a directory only accessible by the current user, with a random
+/// name, see `Self::with_spill_dir`. A single background thread per port moves spilled data of
+/// all its connections back into memory, it only polls while data is spilled.
+pub struct SpillOutput<T, C: Codec = Format> {
+    shared: Arc<Shared<T, C>>,
+    /// Maximum number of items per connection kept in memory
+    threshold: u64,
+    codec: Arc<C>,
+    /// Directory to create the spill directory in
+    base_dir: PathBuf,
+    /// Directory of the spill files, created with the first connection
+    dir: Option<Arc<SpillDir>>,
+    /// Whether the pump thread has been started
+    pumping: bool,
+}
+
+/// Connections of a `SpillOutput`, shared with its pump thread.
+struct Shared<T, C> {
+    connections: Mutex<Vec<SpillConnection<T, C>>>,
+    /// Notified when data has been spilled
+    spilled: Condvar,
+}
+
+impl<T: Clone + Serialize + DeserializeOwned + Send + 'static> SpillOutput<T> {
+    /// Create a port keeping at most `threshold` items per connection in memory.
+    ///
+    /// Spilled data is encoded with `codec::default_format`.
+    pub fn new(threshold: usize) -> Self {
+        Self::with_codec(threshold, codec::default_format())
+    }
+}
+
+impl<T: Clone + Serialize + DeserializeOwned + Send + 'static, C: Codec> SpillOutput<T, C> {
+    /// Like `Self::new`, but encode spilled data with `codec`.
+    pub fn with_codec(threshold: usize, codec: C) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                connections: Mutex::new(Vec::new()),
+                spilled: Condvar::new(),
+            }),
+            threshold: u64::try_from(threshold).unwrap_or(u64::MAX),
+            codec: Arc::new(codec),
+            base_dir: std::env::temp_dir(),
+            dir: None,
+            pumping: false,
+        }
+    }
+
+    /// Create the spill directory in `dir` instead of the temporary directory of the system,
+    /// e.g. on a disk with more space. Takes effect with the first connection.
+    pub fn with_spill_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = dir.into();
+        self
+    }
+
+    /// Connect this output to `input`, see `Output::connect`.
+    ///
+    /// Creates the spill file of the connection, the first connection also creates the spill
+    /// directory and starts the pump thread. The file is removed once the connection is closed,
+    /// the directory once the port and all its connections are gone.
+    pub fn connect(&mut self, input: &mut Input<T>) -> std::io::Result<ConnectionId> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => self.dir.insert(Arc::new(SpillDir::create(&self.base_dir)?)).clone(),
+        };
+        if !self.pumping {
+            let shared = Arc::downgrade(&self.shared);
+            std::thread::Builder::new()
+                .name(crate::thread_name("flexcore-spill".into()))
+                .spawn(move || pump_loop(&shared))?;
+            self.pumping = true;
+        }
+        let (sink, source) = channel();
+        let id = sink.id;
+        let path = dir.0.join(format!("{}", id.0));
+        // Never follows a file planted by someone else.
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        let connection = SpillConnection {
+            handle: sink.handle(),
+            sink,
+            file,
+            path,
+            read_pos: 0,
+            write_pos: 0,
+            spilled: 0,
+            threshold: self.threshold,
+            closed: false,
+            codec: self.codec.clone(),
+            _dir: dir,
+        };
+        self.shared.connections.lock().unwrap_or_else(|e| e.into_inner()).push(connection);
+        input.sources.push(source);
+        Ok(id)
+    }
+
+    /// Write data to this port, see `Output::fire`.
+    ///
+    /// Connections whose input port has been dropped are removed. If data cannot be written to
+    /// the spill file, it is dropped and an error is logged.
+    pub fn fire(&mut self, t: T) {
+        ports::count_fire();
+        let mut connections = self.shared.connections.lock().unwrap_or_else(|e| e.into_inner());
+        let mut spilled = false;
+        connections.retain_mut(|connection| {
+            connection.push(t.clone());
+            if connection.closed {
+                log::debug!("Removing connection {} to dropped input port", connection.handle.id());
+            }
+            spilled |= connection.spilled > 0;
+            !connection.closed
+        });
+        drop(connections);
+        if spilled {
+            self.shared.spilled.notify_one();
+        }
+    }
+
+    /// Number of items currently stored on disk, summed over all connections.
+    pub fn spilled_count(&self) -> usize {
+        let connections = self.shared.connections.lock().unwrap_or_else(|e| e.into_inner());
+        connections.iter().map(|c| c.spilled).sum()
+    }
+}
+
+/// Directory only accessible by the current user holding the spill files of a port.
+///
+/// Removed once the port and all its connections are gone.
+struct SpillDir(PathBuf);
+
+impl SpillDir {
+    /// Create a directory with an unpredictable name in `base`, failing if it already exists.
+    fn create(base: &Path) -> std::io::Result<Self> {
+        use std::hash::{BuildHasher, Hasher};
+        // Seeded with randomness of the OS, so other users cannot guess the name.
+        let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        let path = base.join(format!("flexcore-spill-{}-{random:016x}", std::process::id()));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir(&self.0) {
+            log::warn!("Cannot remove spill directory {}: {e}", self.0.display());
+        }
+    }
+}
+
+/// A connection of a `SpillOutput`.
+struct SpillConnection<T, C> {
+    sink: Sink<T>,
+    /// In-memory depth of the connection
+    handle: ConnectionHandle,
+    file: File,
+    path: PathBuf,
+    /// Position of the next frame to read
+    read_pos: u64,
+    /// Position of the next frame to write
+    write_pos: u64,
+    /// Number of items in the file
+    spilled: usize,
+    threshold: u64,
+    /// Whether the input port has been dropped
+    closed: bool,
+    codec: Arc<C>,
+    /// Kept until the file has been removed
+    _dir: Arc<SpillDir>,
+}
+
+impl<T: Serialize + DeserializeOwned, C: Codec> SpillConnection<T, C> {
+    fn push(&mut self, t: T) {
+        if self.spilled == 0 && self.handle.depth() < self.threshold {
+            self.send(t);
+            return;
+        }
+        if let Err(e) = self.spill(&t) {
+            log::error!("Cannot spill data of connection {} to {}: {e}", self.handle.id(), self.path.display());
+        }
+    }
+
+    fn send(&mut self, t: T) {
+        if !self.sink.send(t) {
+            self.closed = true;
+        }
+    }
+
+    fn spill(&mut self, t: &T) -> std::io::Result<()> {
+        let payload = self.codec.encode(t).map_err(std::io::Error::other)?;
+        let len = u32::try_from(payload.len()).map_err(std::io::Error::other)?;
+        self.file.seek(SeekFrom::Start(self.write_pos))?;
+        self.file.write_all(&len.to_be_bytes())?;
+        self.file.write_all(&payload)?;
+        self.write_pos += 4 + u64::from(len);
+        self.spilled += 1;
+        Ok(())
+    }
+
+    /// Move spilled items back into memory as long as there is room.
+    fn pump(&mut self) -> std::io::Result<()> {
+        while self.spilled > 0 && !self.closed && self.handle.depth() < self.threshold {
+            self.file.seek(SeekFrom::Start(self.read_pos))?;
+            let mut len = [0; 4];
+            self.file.read_exact(&mut len)?;
+            let len = u32::from_be_bytes(len);
+            let mut payload = vec![0; len as usize];
+            self.file.read_exact(&mut payload)?;
+            self.read_pos += 4 + u64::from(len);
+            self.spilled -= 1;
+            if self.spilled == 0 {
+                self.file.set_len(0)?;
+                self.read_pos = 0;
+                self.write_pos = 0;
+            }
+            let t = self.codec.decode(&payload).map_err(std::io::Error::other)?;
+            self.send(t);
+        }
+        Ok(())
+    }
+}
+
+impl<T, C> Drop for SpillConnection<T, C> {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Cannot remove spill file {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Move spilled data of the connections of `shared` back into memory until the port is dropped.
+fn pump_loop<T: Serialize + DeserializeOwned, C: Codec>(shared: &Weak<Shared<T, C>>) {
+    loop {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let mut connections = shared.connections.lock().unwrap_or_else(|e| e.into_inner());
+        if connections.iter().all(|c| c.spilled == 0) {
+            let _ = shared
+                .spilled
+                .wait_timeout(connections, PUMP_IDLE_TIMEOUT)
+                .unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+        for connection in connections.iter_mut() {
+            if let Err(e) = connection.pump() {
+                log::error!(
+                    "Cannot read spilled data of connection {} from {}, dropping it: {e}",
+                    connection.handle.id(),
+                    connection.path.display()
+                );
+                connection.spilled = 0;
+                connection.read_pos = 0;
+                connection.write_pos = 0;
+            }
+        }
+        connections.retain(|c| !c.closed);
+        drop(connections);
+        drop(shared);
+        std::thread::sleep(PUMP_INTERVAL);
+    }
+}
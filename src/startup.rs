@@ -0,0 +1,62 @@
+//! Coordination of starting the region threads.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::shutdown::Shutdown;
+
+/// Startup state shared by all region threads of an infrastructure.
+///
+/// Regions start in ascending order of their startup phase: a region calls `Node::on_start` of
+/// its nodes once all regions of lower phases have done so.
+#[derive(Debug, Default)]
+pub(crate) struct Startup {
+    /// Number of regions per startup phase that have not finished starting
+    pending: Mutex<BTreeMap<u32, usize>>,
+    started: Condvar,
+}
+
+/// Interval in which waiting regions check whether shutdown has been requested.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl Startup {
+    /// Mark a region of startup phase `phase` as starting until the returned guard is dropped.
+    pub(crate) fn register(self: &Arc<Self>, phase: u32) -> StartingGuard {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        *pending.entry(phase).or_default() += 1;
+        StartingGuard {
+            startup: self.clone(),
+            phase,
+        }
+    }
+
+    /// Block until all regions of phases lower than `phase` have started.
+    ///
+    /// Returns early once shutdown is requested, so a hanging start does not block shutdown.
+    pub(crate) fn wait(&self, phase: u32, shutdown: &Shutdown) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        while !pending.range(..phase).all(|(_, n)| *n == 0) && !shutdown.is_requested() {
+            pending = self
+                .started
+                .wait_timeout(pending, SHUTDOWN_POLL_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+/// Marks a region as starting while alive, see `Startup::register`.
+pub(crate) struct StartingGuard {
+    startup: Arc<Startup>,
+    phase: u32,
+}
+
+impl Drop for StartingGuard {
+    fn drop(&mut self) {
+        let mut pending = self.startup.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(n) = pending.get_mut(&self.phase) {
+            *n = n.saturating_sub(1);
+        }
+        self.startup.started.notify_all();
+    }
+}
@@ -0,0 +1,97 @@
+//! Ports carrying a single current value instead of a stream of items.
+//!
+//! Some data is state rather than a sequence of events, e.g. the current setpoint or
+//! configuration. Sent over a connection, a consumer would have to drain the backlog to get to
+//! the latest value. A `StateOutput` instead overwrites a shared value with `StateOutput::set`,
+//! and connected `StateInput` ports read the value current at that time with
+//! `StateInput::get`. Neither side ever blocks for longer than the copy of the value, and
+//! intermediate values that nobody read are lost by design.
+//!
+//! ```
+//! use flexcore::{StateInput, StateOutput};
+//!
+//! let mut output = StateOutput::default();
+//! let mut input = StateInput::default();
+//! output.connect(&mut input);
+//! assert_eq!(input.get(), None);
+//! output.set(1.5);
+//! output.set(2.0);
+//! assert_eq!(input.get(), Some(2.0));
+//! ```
+use std::sync::{Arc, Mutex};
+
+/// Value shared by a state output and its inputs.
+#[derive(Debug)]
+struct Shared<T> {
+    value: Option<T>,
+    /// Number of `StateOutput::set` calls
+    version: u64,
+}
+
+type Cell<T> = Arc<Mutex<Shared<T>>>;
+
+fn new_cell<T>() -> Cell<T> {
+    Arc::new(Mutex::new(Shared { value: None, version: 0 }))
+}
+
+/// Output port providing a current value, see the module documentation.
+pub struct StateOutput<T> {
+    cell: Cell<T>,
+}
+
+impl<T> Default for StateOutput<T> {
+    fn default() -> Self {
+        Self { cell: new_cell() }
+    }
+}
+
+impl<T> StateOutput<T> {
+    /// Let `input` read the value of this port from now on.
+    ///
+    /// Any number of inputs can be connected. An input is connected to at most one output, a
+    /// previous connection of `input` is replaced.
+    pub fn connect(&self, input: &mut StateInput<T>) {
+        input.cell = self.cell.clone();
+        input.seen = 0;
+    }
+
+    /// Replace the current value.
+    pub fn set(&mut self, t: T) {
+        let mut shared = self.cell.lock().unwrap_or_else(|e| e.into_inner());
+        shared.value = Some(t);
+        shared.version += 1;
+    }
+}
+
+/// Input port reading the current value of a `StateOutput`, see the module documentation.
+pub struct StateInput<T> {
+    cell: Cell<T>,
+    /// Version of the value returned last, see `Self::get_changed`
+    seen: u64,
+}
+
+impl<T> Default for StateInput<T> {
+    fn default() -> Self {
+        Self { cell: new_cell(), seen: 0 }
+    }
+}
+
+impl<T: Clone> StateInput<T> {
+    /// The current value, `None` if the port is not connected or no value has been set yet.
+    pub fn get(&mut self) -> Option<T> {
+        let shared = self.cell.lock().unwrap_or_else(|e| e.into_inner());
+        self.seen = shared.version;
+        shared.value.clone()
+    }
+
+    /// Like `Self::get`, but `None` unless a value has been set since the last call of either,
+    /// e.g. to apply a new configuration only once.
+    pub fn get_changed(&mut self) -> Option<T> {
+        let shared = self.cell.lock().unwrap_or_else(|e| e.into_inner());
+        if shared.version == self.seen {
+            return None;
+        }
+        self.seen = shared.version;
+        shared.value.clone()
+    }
+}
@@ -0,0 +1,139 @@
+//! Liveness checks of running regions, see `Infrastructure::enable_supervisor`.
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::RegionCounters;
+use crate::region::RestartPolicy;
+use crate::shutdown::Shutdown;
+
+/// Delay of the first restart of a failed region, see `RestartPolicy::Always`.
+const MIN_RESTART_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Delay of restarts of a region that keeps failing, also the time a restarted region has to
+/// run without failing to be restarted without delay again.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Progress of a region as last seen by the supervisor.
+struct Progress {
+    counters: Arc<RegionCounters>,
+    /// Finished iterations at the last check that saw them change
+    iterations: u64,
+    /// Time of that check
+    since: Instant,
+    stalled: bool,
+    /// Restarts since the region last ran for `MAX_RESTART_BACKOFF` without failing
+    restarts: u32,
+    /// Time of the last restart
+    restarted: Option<Instant>,
+    /// Time of the next restart of the failed region
+    restart_at: Option<Instant>,
+}
+
+/// Check `regions` every `interval` until shutdown is requested, keeping `stalled` up to date
+/// with the names of the regions that have not finished an iteration for longer than `threshold`.
+///
+/// Failed regions with `RestartPolicy::Always` are restarted, with a delay doubling for each
+/// restart from `MIN_RESTART_BACKOFF` to `MAX_RESTART_BACKOFF`.
+pub(crate) fn watch(
+    regions: Vec<Arc<RegionCounters>>,
+    shutdown: &Shutdown,
+    interval: Duration,
+    threshold: Duration,
+    stalled: &Mutex<Vec<String>>,
+) {
+    let now = Instant::now();
+    let mut progress: Vec<Progress> = regions
+        .into_iter()
+        .map(|counters| Progress {
+            iterations: counters.iterations.load(Ordering::Relaxed),
+            counters,
+            since: now,
+            stalled: false,
+            restarts: 0,
+            restarted: None,
+            restart_at: None,
+        })
+        .collect();
+    while !shutdown.is_requested() {
+        let generation = shutdown.generation();
+        shutdown.sleep_until(Instant::now() + interval, generation);
+        let now = Instant::now();
+        for region in &mut progress {
+            if region.counters.stopped.load(Ordering::Relaxed) {
+                region.stalled = false;
+                continue;
+            }
+            if region.counters.restart_policy == RestartPolicy::Always && supervise_restart(region, shutdown, now) {
+                continue;
+            }
+            let iterations = region.counters.iterations.load(Ordering::Relaxed);
+            // Waiting for a fire is no stall, only not running for one is.
+            let waiting = region.counters.trigger.as_ref().is_some_and(|t| !t.is_pending());
+            if iterations != region.iterations || waiting {
+                if region.stalled {
+                    log::info!("Region {} is making progress again", region.counters.name);
+                }
+                region.iterations = iterations;
+                region.since = now;
+                region.stalled = false;
+            } else if !region.stalled && now - region.since > threshold {
+                log::warn!(
+                    "Region {} has not finished an iteration for {} s",
+                    region.counters.name,
+                    (now - region.since).as_secs_f64()
+                );
+                region.stalled = true;
+            }
+        }
+        *stalled.lock().unwrap_or_else(|e| e.into_inner()) = progress
+            .iter()
+            .filter(|r| r.stalled)
+            .map(|r| r.counters.name.clone())
+            .collect();
+    }
+}
+
+/// Restart `region` once its backoff has passed if it failed, returns whether it failed.
+fn supervise_restart(region: &mut Progress, shutdown: &Shutdown, now: Instant) -> bool {
+    let failure = region.counters.failure.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let Some(failure) = failure else {
+        if region.restarted.is_some_and(|t| now - t > MAX_RESTART_BACKOFF) {
+            region.restarts = 0;
+            region.restarted = None;
+        }
+        return false;
+    };
+    // A failed region is not stalled, its progress counts from the restart.
+    region.stalled = false;
+    region.since = now;
+    if region.counters.restart_requested.load(Ordering::SeqCst) {
+        return true;
+    }
+    let Some(restart_at) = region.restart_at else {
+        let backoff = MIN_RESTART_BACKOFF
+            .saturating_mul(1 << region.restarts.min(16))
+            .min(MAX_RESTART_BACKOFF);
+        log::warn!(
+            "Region {} failed, restarting in {} s: {failure}",
+            region.counters.name,
+            backoff.as_secs_f64()
+        );
+        region.restart_at = Some(now + backoff);
+        return true;
+    };
+    if now >= restart_at {
+        region.restarts += 1;
+        log::warn!(
+            "Restarting region {} after a panic, restart {} in a row: {failure}",
+            region.counters.name,
+            region.restarts
+        );
+        region.restart_at = None;
+        region.restarted = Some(now);
+        region.counters.restart_requested.store(true, Ordering::SeqCst);
+        // Wakes the region thread.
+        shutdown.notify_change();
+    }
+    true
+}
@@ -0,0 +1,59 @@
+//! Helpers for testing pipelines.
+//!
+//! Observe outputs with `Output::subscribe` or `Infrastructure::subscribe` and wait for the
+//! expected data with a timeout instead of sleeping for a fixed time.
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::Infrastructure;
+
+pub mod bench;
+
+/// Receive up to `n` items from `subscription`, waiting at most `timeout` in total.
+///
+/// Returns fewer than `n` items if the timeout elapses or the output port is dropped.
+pub fn collect_n<T>(subscription: &Receiver<T>, n: usize, timeout: Duration) -> Vec<T> {
+    let deadline = Instant::now() + timeout;
+    let mut ret = Vec::with_capacity(n);
+    while ret.len() < n {
+        match subscription.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(t) => ret.push(t),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    ret
+}
+
+/// Assert that the output port `output` of node `node` in region `region` fires an item matching
+/// `predicate` within `timeout`.
+///
+/// Only items fired after this call are considered. Returns the first matching item.
+///
+/// # Panics
+///
+/// If the port cannot be subscribed to, see `Infrastructure::subscribe`, or no matching item is
+/// fired in time.
+pub fn assert_emits<T: std::fmt::Debug + Send + 'static>(
+    infra: &Infrastructure,
+    region: &str,
+    node: &str,
+    output: &str,
+    predicate: impl Fn(&T) -> bool,
+    timeout: Duration,
+) -> T {
+    let subscription = match infra.subscribe::<T>(region, node, output) {
+        Ok(subscription) => subscription,
+        Err(e) => panic!("Cannot subscribe to {region}/{node}.{output}: {e:?}"),
+    };
+    let deadline = Instant::now() + timeout;
+    let mut seen = Vec::new();
+    loop {
+        match subscription.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(t) if predicate(&t) => return t,
+            Ok(t) => seen.push(t),
+            Err(_) => panic!(
+                "{region}/{node}.{output} did not emit a matching item within {timeout:?}, got {seen:?}"
+            ),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Throughput and latency measurement of a canonical pipeline.
+//!
+//! Used by the benchmarks in `benches/` as a regression signal for changes of the port
+//! internals, and available to measure the overhead of flexcore on a given machine.
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::node::{should_yield, Node};
+use crate::ports::{Input, Output};
+use crate::{FlexcoreError, InfrastructureBuilder, ShutdownToken};
+
+/// Kind of connections used by a `BenchPipeline`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BenchChannel {
+    /// See `Output::connect`
+    Unbounded,
+    /// See `Output::connect_bounded`. The producer waits for space instead of dropping data.
+    Bounded(usize),
+}
+
+/// Producer→processor→sink pipeline driving a number of messages through its regions.
+///
+/// The producer fires to `fan_out` processors, each running in its own region and forwarding
+/// to a common sink. Every message therefore reaches the sink `fan_out` times.
+///
+/// ```
+/// use flexcore::test::bench::{BenchChannel, BenchPipeline};
+///
+/// let result = BenchPipeline::new()
+///     .with_fan_out(2)
+///     .with_channel(BenchChannel::Bounded(16))
+///     .run(100)
+///     .unwrap();
+/// assert_eq!(result.received, 200);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BenchPipeline {
+    fan_out: usize,
+    channel: BenchChannel,
+    tick: Duration,
+    timeout: Duration,
+}
+
+impl Default for BenchPipeline {
+    fn default() -> Self {
+        Self {
+            fan_out: 1,
+            channel: BenchChannel::Unbounded,
+            tick: Duration::from_millis(1),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl BenchPipeline {
+    /// Single processor, unbounded connections and a tick of 1 ms in all regions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of processors the producer fires to, at least 1.
+    pub fn with_fan_out(mut self, fan_out: usize) -> Self {
+        self.fan_out = fan_out.max(1);
+        self
+    }
+
+    /// Kind of all connections of the pipeline.
+    pub fn with_channel(mut self, channel: BenchChannel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Tick of all regions of the pipeline.
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Give up waiting for the sink after `timeout`, 10 s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Build the pipeline, fire `messages` messages from the producer and wait until the sink
+    /// has received all of them or the timeout elapses.
+    ///
+    /// Only the time from starting the infrastructure until the sink has received the last
+    /// message is measured, building and stopping it is not.
+    pub fn run(&self, messages: usize) -> Result<BenchResult, FlexcoreError> {
+        let expected = messages * self.fan_out;
+        let stats = Arc::new(Mutex::new(SinkStats::default()));
+        let (done_tx, done_rx) = mpsc::channel();
+
+        let mut infra = InfrastructureBuilder::default().with_thread_name_prefix("bench");
+        let mut producer = Producer {
+            name: "producer".into(),
+            remaining: messages,
+            shutdown: infra.shutdown_token(),
+            out: Output::default(),
+        };
+        let mut sink = Sink {
+            name: "sink".into(),
+            expected,
+            stats: stats.clone(),
+            done: Some(done_tx),
+            input: Input::default(),
+        };
+        let mut processors = Vec::with_capacity(self.fan_out);
+        for i in 0..self.fan_out {
+            let mut processor = Processor {
+                name: format!("processor-{i}"),
+                shutdown: infra.shutdown_token(),
+                input: Input::default(),
+                out: Output::default(),
+            };
+            self.connect(&mut producer.out, &mut processor.input);
+            self.connect(&mut processor.out, &mut sink.input);
+            processors.push(processor);
+        }
+
+        infra = infra.with_region("sink", self.tick).with_node(sink).build()?;
+        for processor in processors {
+            let region = processor.name.clone();
+            infra = infra.with_region(region, self.tick).with_node(processor).build()?;
+        }
+        infra = infra.with_region("producer", self.tick).with_node(producer).build()?;
+
+        let start = Instant::now();
+        let infra = infra.build()?;
+        // On timeout the statistics collected so far are returned.
+        if expected > 0 {
+            let _ = done_rx.recv_timeout(self.timeout);
+        }
+        infra.shutdown();
+
+        let stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(BenchResult {
+            received: stats.received,
+            elapsed: stats.last.map_or(start.elapsed(), |last| last.saturating_duration_since(start)),
+            mean_latency: u32::try_from(stats.received)
+                .ok()
+                .and_then(|n| stats.latency_sum.checked_div(n))
+                .unwrap_or_default(),
+            max_latency: stats.latency_max,
+        })
+    }
+
+    fn connect(&self, output: &mut Output<BenchMessage>, input: &mut Input<BenchMessage>) {
+        match self.channel {
+            BenchChannel::Unbounded => output.connect(input),
+            BenchChannel::Bounded(capacity) => output.connect_bounded(input, capacity),
+        };
+    }
+}
+
+/// Outcome of `BenchPipeline::run`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    /// Number of messages that reached the sink, fewer than expected on timeout
+    pub received: usize,
+    /// Time from starting the infrastructure until the last message reached the sink
+    pub elapsed: Duration,
+    /// Mean time from firing a message in the producer until it reached the sink
+    pub mean_latency: Duration,
+    /// Longest time from firing a message in the producer until it reached the sink
+    pub max_latency: Duration,
+}
+
+impl BenchResult {
+    /// Messages received by the sink per second.
+    pub fn throughput(&self) -> f64 {
+        self.received as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BenchMessage {
+    fired: Instant,
+}
+
+#[derive(Debug, Default)]
+struct SinkStats {
+    received: usize,
+    latency_sum: Duration,
+    latency_max: Duration,
+    last: Option<Instant>,
+}
+
+struct Producer {
+    name: String,
+    remaining: usize,
+    shutdown: ShutdownToken,
+    out: Output<BenchMessage>,
+}
+
+impl Node for Producer {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn tick(&mut self) {
+        while self.remaining > 0 && !should_yield() {
+            let message = BenchMessage { fired: Instant::now() };
+            if self.out.fire_blocking(message, &self.shutdown).is_err() {
+                return;
+            }
+            self.remaining -= 1;
+        }
+    }
+}
+
+struct Processor {
+    name: String,
+    shutdown: ShutdownToken,
+    input: Input<BenchMessage>,
+    out: Output<BenchMessage>,
+}
+
+impl Node for Processor {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        for message in self.input.fetch() {
+            if self.out.fire_blocking(message, &self.shutdown).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct Sink {
+    name: String,
+    expected: usize,
+    stats: Arc<Mutex<SinkStats>>,
+    done: Option<Sender<()>>,
+    input: Input<BenchMessage>,
+}
+
+impl Node for Sink {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        let messages = self.input.fetch();
+        if messages.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        for message in &messages {
+            let latency = now.saturating_duration_since(message.fired);
+            stats.latency_sum += latency;
+            stats.latency_max = stats.latency_max.max(latency);
+        }
+        stats.received += messages.len();
+        stats.last = Some(now);
+        if stats.received >= self.expected {
+            if let Some(done) = self.done.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Declarative description of an infrastructure.
+//!
+//! A `Topology` lists regions, their nodes, and the connections between named ports. Nodes are
+//! instantiated by factories registered in a `NodeRegistry` under a node type name. With the
+//! `serde` feature, `Topology` can be deserialized, e.g. from a configuration file.
+use std::collections::HashMap;
+
+use crate::{FlexcoreError, InfrastructureBuilder, Node};
+
+/// Complete description of regions, nodes and connections.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Topology {
+    pub regions: Vec<RegionSpec>,
+    pub connections: Vec<ConnectionSpec>,
+}
+
+/// Description of a region.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegionSpec {
+    pub name: String,
+    /// Work tick duration in seconds
+    pub tick_secs: f64,
+    pub nodes: Vec<NodeSpec>,
+}
+
+/// Description of a node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeSpec {
+    /// Name of the node, unique in the whole topology
+    pub name: String,
+    /// Name the node factory is registered under, see `NodeRegistry::register_node_type`
+    pub kind: String,
+}
+
+/// Description of a connection from an output port to an input port.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionSpec {
+    pub from_node: String,
+    pub from_port: String,
+    pub to_node: String,
+    pub to_port: String,
+}
+
+/// Creates a node with the given name.
+pub type NodeFactory = Box<dyn Fn(&str) -> Box<dyn Node>>;
+
+/// Node factories by node type name.
+#[derive(Default)]
+pub struct NodeRegistry {
+    factories: HashMap<String, NodeFactory>,
+}
+
+impl NodeRegistry {
+    /// Register `factory` to create nodes of type `kind`.
+    ///
+    /// The factory is called with the name of the node to create. Registering a type again
+    /// replaces the previous factory.
+    pub fn register_node_type(
+        &mut self,
+        kind: impl Into<String>,
+        factory: impl Fn(&str) -> Box<dyn Node> + 'static,
+    ) {
+        self.factories.insert(kind.into(), Box::new(factory));
+    }
+
+    fn create(&self, kind: &str, name: &str) -> Result<Box<dyn Node>, FlexcoreError> {
+        let factory = self.factories.get(kind).ok_or_else(|| {
+            log::error!("Cannot create node {name}: unknown node type {kind}");
+            FlexcoreError::UnknownNodeType
+        })?;
+        Ok(factory(name))
+    }
+}
+
+impl InfrastructureBuilder {
+    /// Create all regions and nodes of `topology` and connect their ports.
+    ///
+    /// Connections are made by port name, see `InfrastructureBuilder::connect`.
+    pub fn from_topology(topology: &Topology, registry: &NodeRegistry) -> Result<Self, FlexcoreError> {
+        let mut node_names = std::collections::HashSet::new();
+        for node in topology.regions.iter().flat_map(|r| &r.nodes) {
+            if !node_names.insert(node.name.as_str()) {
+                log::error!("Node {} is defined more than once", node.name);
+                return Err(FlexcoreError::DuplicateNode);
+            }
+        }
+        let mut infra = InfrastructureBuilder::default();
+        for region in &topology.regions {
+            let tick = crate::region::tick_from_secs(region.tick_secs)?;
+            let mut region_builder = infra.with_region(region.name.clone(), tick);
+            for node in &region.nodes {
+                region_builder = region_builder.with_boxed_node(registry.create(&node.kind, &node.name)?);
+            }
+            infra = region_builder.build()?;
+        }
+        for c in &topology.connections {
+            infra.connect(&c.from_node, &c.from_port, &c.to_node, &c.to_port)?;
+        }
+        Ok(infra)
+    }
+}
@@ -0,0 +1,165 @@
+//! Connecting ports looked up by name, whose types are only known at runtime.
+use std::time::{Duration, Instant};
+
+use flexcore::ports::{AnyInput, AnyOutput};
+use flexcore::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+struct SensorData {
+    data: [f64; 3],
+}
+
+ports! {
+    struct SensorPorts {
+        in: [],
+        out: [measurements: SensorData],
+    }
+}
+
+ports! {
+    struct ProcessingPorts {
+        in: [measurements: SensorData, velocity: f64],
+        out: [],
+    }
+}
+
+/// Fires one measurement per tick.
+struct Sensor {
+    name: String,
+    ports: SensorPorts,
+}
+
+impl Sensor {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ports: SensorPorts::default(),
+        }
+    }
+}
+
+impl Node for Sensor {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn tick(&mut self) {
+        self.ports.measurements.fire(SensorData { data: [1.0, 2.0, 3.0] });
+    }
+
+    fn output_port(&mut self, name: &str) -> Option<&mut dyn AnyOutput> {
+        self.ports.output_port(name)
+    }
+
+    fn output_port_names(&self) -> &'static [&'static str] {
+        SensorPorts::OUTPUTS
+    }
+}
+
+/// Collects everything it receives.
+struct Processing {
+    name: String,
+    ports: ProcessingPorts,
+    received: Vec<SensorData>,
+}
+
+impl Processing {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ports: ProcessingPorts::default(),
+            received: Vec::new(),
+        }
+    }
+}
+
+impl Node for Processing {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn process_input(&mut self) {
+        let data = self.ports.measurements.fetch();
+        self.received.extend(data);
+    }
+
+    fn input_port(&mut self, name: &str) -> Option<&mut dyn AnyInput> {
+        self.ports.input_port(name)
+    }
+
+    fn input_port_names(&self) -> &'static [&'static str] {
+        ProcessingPorts::INPUTS
+    }
+}
+
+fn type_mismatch() -> FlexcoreError {
+    FlexcoreError::PortTypeMismatch {
+        output_type: std::any::type_name::<SensorData>(),
+        input_type: std::any::type_name::<f64>(),
+    }
+}
+
+#[test]
+fn mismatched_ports_by_name_fail_to_connect() {
+    let mut sensor = Sensor::new("sensor");
+    let mut processing = Processing::new("processing");
+    let output = sensor.output_port("measurements").unwrap();
+    let input = processing.input_port("velocity").unwrap();
+
+    assert_eq!(output.connect(input), Err(type_mismatch()));
+    assert_eq!(output.connection_count(), 0);
+    assert_eq!(processing.input_port("velocity").unwrap().connection_count(), 0);
+}
+
+#[test]
+fn matching_ports_by_name_deliver_data() {
+    let mut sensor = Sensor::new("sensor");
+    let mut processing = Processing::new("processing");
+    let output = sensor.output_port("measurements").unwrap();
+    let input = processing.input_port("measurements").unwrap();
+
+    let id = output.connect(input).unwrap();
+    assert_eq!(output.connection_ids(), vec![id]);
+    sensor.tick();
+    processing.process_input();
+    assert_eq!(processing.received, vec![SensorData { data: [1.0, 2.0, 3.0] }]);
+}
+
+fn builder() -> InfrastructureBuilder {
+    InfrastructureBuilder::default()
+        .with_region("sensors", Duration::from_millis(5))
+        .with_node(Sensor::new("sensor"))
+        .build()
+        .unwrap()
+        .with_region("processing", Duration::from_millis(5))
+        .with_node(Processing::new("processing"))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn builder_rejects_mismatched_connection() {
+    let mut builder = builder();
+
+    assert_eq!(builder.connect("sensor", "measurements", "processing", "velocity"), Err(type_mismatch()));
+    assert!(builder.graph().edges.is_empty());
+}
+
+#[test]
+fn builder_connection_delivers_data() {
+    let mut builder = builder();
+    builder.connect("sensor", "measurements", "processing", "measurements").unwrap();
+    let infra = builder.build().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let received = loop {
+        let received = infra
+            .with_node("processing", "processing", |n: &mut Processing| n.received.clone())
+            .unwrap();
+        if !received.is_empty() || Instant::now() > deadline {
+            break received;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    assert_eq!(received[0], SensorData { data: [1.0, 2.0, 3.0] });
+}